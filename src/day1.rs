@@ -1,3 +1,41 @@
+static DIGIT_WORDS: [&str; 9] = [ /* "zero", */ "one", "two", "three", "four", "five", "six", "seven", "eight", "nine" ];
+
+/// First code point of each run of ten decimal digits (Unicode category Nd).
+static UNICODE_ZEROS: [u32; 68] = [
+    0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6,
+    0x0C66, 0x0CE6, 0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0,
+    0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620,
+    0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10, 0x104A0, 0x10D30, 0x11066,
+    0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650, 0x116C0, 0x11730, 0x118E0,
+    0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x11F50, 0x16A60, 0x16AC0, 0x16B50, 0x1D7CE, 0x1D7D8,
+    0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E140, 0x1E2F0, 0x1E4F0, 0x1E950, 0x1FBF0,
+];
+
+/// Which characters count as written digits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Digits {
+    Ascii,
+    /// Any Unicode decimal digit, e.g. fullwidth `３` or Devanagari `३`.
+    Unicode,
+}
+
+impl Digits {
+    fn value(self, c: char) -> Option<u32> {
+        match self {
+            Digits::Ascii => c.to_digit(10),
+            Digits::Unicode => {
+                let c = c as u32;
+                let run = UNICODE_ZEROS.partition_point(|zero| *zero <= c);
+                if run == 0 {
+                    return None;
+                }
+                let offset = c - UNICODE_ZEROS[run - 1];
+                if offset < 10 { Some(offset) } else { None }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Line {
     digits: Vec<u32>,
@@ -20,9 +58,20 @@ impl Line {
         10 * first + last
     }
 
-    fn match_digit_word_prefix(s: &str, at: usize) -> Option<(u32, usize)> {
-        static DIGIT_WORDS: [&str; 9] = [ /* "zero", */ "one", "two", "three", "four", "five", "six", "seven", "eight", "nine" ];
+    fn parse_with(s: &str, digits: Digits) -> Line {
+        let mut line = Line::new();
+        for (i, c) in s.char_indices() {
+            if let Some((digit, _len)) = Line::match_digit_word_prefix(s, i) {
+                // oneight...
+                line.visit_digit(digit);
+            } else if let Some(digit) = digits.value(c) {
+                line.visit_digit(digit);
+            }
+        }
+        line
+    }
 
+    fn match_digit_word_prefix(s: &str, at: usize) -> Option<(u32, usize)> {
         let mut result = None;
         for (digit, word) in DIGIT_WORDS.iter().enumerate() {
             // println!("word = {}, word.len = {}, digit = {}, at = {}, s.len = {}", word, word.len(), digit, at, s.len());
@@ -43,32 +92,113 @@ impl std::str::FromStr for Line {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut i = 0;
-        let mut line = Line::new();
-        while i < s.len() {
-            if let Some((digit, _len)) = Line::match_digit_word_prefix(s, i) {
-                line.visit_digit(digit);
-                // oneight...
-                i += 1;
-            } else {
-                let sl = s.get(i..i+1).unwrap();
-                // println!("sl = {}, i = {}", sl, i);
-                let c = sl.chars().next().unwrap();
-                if let Some(digit) = c.to_digit(10) {
-                    line.visit_digit(digit);
-                }
-                i += 1;
-            }
+        Ok(Line::parse_with(s, Digits::Ascii))
+    }
+}
+
+/// Digit (written or spelled) starting with the character `c` at byte `at` of `s`.
+fn digit_at(s: &str, at: usize, c: char, digits: Digits) -> Option<u32> {
+    if let Some(digit) = digits.value(c) {
+        return Some(digit);
+    }
+    for (digit, word) in DIGIT_WORDS.iter().enumerate() {
+        if s[at..].starts_with(word) {
+            return Some((digit + 1) as u32);
+        }
+    }
+    None
+}
+
+/// Value of a single line, found by scanning from the left for the first digit and
+/// from the right for the last digit, without collecting everything in between.
+fn scan_line_value(s: &str, digits: Digits) -> Option<u32> {
+    let first = s.char_indices().find_map(|(i, c)| digit_at(s, i, c, digits))?;
+    // Digits can overlap ("oneight"), so the right-to-left scan must still match
+    // words by their start position: it never misses the last one.
+    let last = s.char_indices().rev().find_map(|(i, c)| digit_at(s, i, c, digits))?;
+    Some(10 * first + last)
+}
+
+fn calculate_calibration_value_fast(input: &str, digits: Digits) -> u32 {
+    input.lines().map(|line| scan_line_value(line, digits).unwrap()).sum()
+}
+
+/// How the digits of a line are turned into its value.
+trait LineValuePolicy {
+    /// `None` if the line has no value under this policy.
+    fn value(&self, line: &Line) -> Option<u64>;
+}
+
+/// `10 * first + last`, the puzzle's rule.
+struct FirstLast;
+
+/// All digits of the line read as one number.
+struct Concatenated;
+
+/// Largest two-digit number made from two digits in line order. A lone digit
+/// pairs with itself, like for `FirstLast`.
+struct MaxPair;
+
+struct SumOfDigits;
+
+impl LineValuePolicy for FirstLast {
+    fn value(&self, line: &Line) -> Option<u64> {
+        if line.digits.is_empty() {
+            return None;
         }
-        Ok(line)
+        Some(line.value() as u64)
     }
 }
 
-fn calculate_calibration_value(input: &str) -> u32 {
+impl LineValuePolicy for Concatenated {
+    fn value(&self, line: &Line) -> Option<u64> {
+        if line.digits.is_empty() {
+            return None;
+        }
+        line.digits.iter().try_fold(0_u64, |acc, digit| acc.checked_mul(10)?.checked_add(*digit as u64))
+    }
+}
+
+impl LineValuePolicy for MaxPair {
+    fn value(&self, line: &Line) -> Option<u64> {
+        let (first, rest) = line.digits.split_first()?;
+        if rest.is_empty() {
+            return Some(11 * *first as u64);
+        }
+        let mut best = 0;
+        let mut max_tens = *first;
+        for digit in rest {
+            best = std::cmp::max(best, 10 * max_tens + digit);
+            max_tens = std::cmp::max(max_tens, *digit);
+        }
+        Some(best as u64)
+    }
+}
+
+impl LineValuePolicy for SumOfDigits {
+    fn value(&self, line: &Line) -> Option<u64> {
+        if line.digits.is_empty() {
+            return None;
+        }
+        Some(line.digits.iter().map(|digit| *digit as u64).sum())
+    }
+}
+
+fn line_value_policy(name: &str) -> Option<Box<dyn LineValuePolicy>> {
+    match name {
+        "first-last" => Some(Box::new(FirstLast)),
+        "concatenated" => Some(Box::new(Concatenated)),
+        "max-pair" => Some(Box::new(MaxPair)),
+        "sum" => Some(Box::new(SumOfDigits)),
+        _ => None,
+    }
+}
+
+fn calculate_calibration_value<P: LineValuePolicy + ?Sized>(input: &str, digits: Digits, policy: &P) -> u64 {
     let mut result = [].to_vec();
     for (index, line) in input.lines().enumerate() {
-        let parsed_line = line.parse::<Line>().unwrap();
-        let value = parsed_line.value();
+        let parsed_line = Line::parse_with(line, digits);
+        let value = policy.value(&parsed_line).unwrap();
         println!("index = {}, line = {}, parsed_line = {:?}, value = {}", index, line, parsed_line, value);
         result.push(value);
     }
@@ -77,8 +207,27 @@ fn calculate_calibration_value(input: &str) -> u32 {
 }
 
 pub fn main() {
+    let mut digits = Digits::Ascii;
+    let mut policy: Box<dyn LineValuePolicy> = Box::new(FirstLast);
+    for arg in std::env::args().skip(1) {
+        if arg == "--unicode-digits" {
+            digits = Digits::Unicode;
+        } else if let Some(name) = arg.strip_prefix("--policy=") {
+            match line_value_policy(name) {
+                Some(p) => policy = p,
+                None => {
+                    println!("error = unknown policy {} (first-last, concatenated, max-pair, sum)", name);
+                    return
+                }
+            }
+        }
+    }
+
     match std::fs::read_to_string("day1.input") {
-        Ok(input) => println!("value = {}", calculate_calibration_value(&input)),
+        Ok(input) => {
+            println!("value = {}", calculate_calibration_value(&input, digits, policy.as_ref()));
+            println!("value (fast, first-last) = {}", calculate_calibration_value_fast(&input, digits));
+        },
         Err(reason) => println!("error = {}", reason)
     }
 }
@@ -120,7 +269,7 @@ mod tests {
 pqr3stu8vwx
 a1b2c3d4e5f
 treb7uchet";
-        let value = calculate_calibration_value(DATA);
+        let value = calculate_calibration_value(DATA, Digits::Ascii, &FirstLast);
         assert_eq!(142, value);
     }
 
@@ -133,13 +282,145 @@ xtwone3four
 4nineeightseven2
 zoneight234
 7pqrstsixteen";
-        let value = calculate_calibration_value(DATA);
+        let value = calculate_calibration_value(DATA, Digits::Ascii, &FirstLast);
         assert_eq!(281, value);
     }
 
     #[test]
     fn calculate_calibration_value_oneight() {
-        let result = calculate_calibration_value("oneight");
+        let result = calculate_calibration_value("oneight", Digits::Ascii, &FirstLast);
         assert_eq!(result, 18);
     }
+
+    #[test]
+    fn scan_line_value_overlapping_words() {
+        assert_eq!(scan_line_value("oneight", Digits::Ascii), Some(18));
+        assert_eq!(scan_line_value("xtwone3four", Digits::Ascii), Some(24));
+        assert_eq!(scan_line_value("eighthree", Digits::Ascii), Some(83));
+        assert_eq!(scan_line_value("7pqrstsixteen", Digits::Ascii), Some(76));
+        assert_eq!(scan_line_value("abc", Digits::Ascii), None);
+    }
+
+    #[test]
+    fn calculate_calibration_value_fast_example2() {
+        static DATA: &str = "two1nine
+eightwothree
+abcone2threexyz
+xtwone3four
+4nineeightseven2
+zoneight234
+7pqrstsixteen";
+        assert_eq!(calculate_calibration_value_fast(DATA, Digits::Ascii), 281);
+    }
+
+    #[test]
+    fn line_value_policies() {
+        let line = "4nineeightseven2".parse::<Line>().unwrap();
+        assert_eq!(FirstLast.value(&line), Some(42));
+        assert_eq!(Concatenated.value(&line), Some(49872));
+        assert_eq!(MaxPair.value(&line), Some(98));
+        assert_eq!(SumOfDigits.value(&line), Some(30));
+
+        let single = "a7b".parse::<Line>().unwrap();
+        assert_eq!(MaxPair.value(&single), Some(77));
+
+        let empty = "abc".parse::<Line>().unwrap();
+        assert_eq!(Concatenated.value(&empty), None);
+        assert_eq!(MaxPair.value(&empty), None);
+    }
+
+    #[test]
+    fn line_value_concatenated_overflow() {
+        let line = "123456789012345678901".parse::<Line>().unwrap();
+        assert_eq!(Concatenated.value(&line), None);
+    }
+
+    #[test]
+    fn calculate_calibration_value_policy_by_name() {
+        static DATA: &str = "two1nine
+eightwothree";
+        assert_eq!(calculate_calibration_value(DATA, Digits::Ascii, line_value_policy("first-last").unwrap().as_ref()), 29 + 83);
+        assert_eq!(calculate_calibration_value(DATA, Digits::Ascii, line_value_policy("concatenated").unwrap().as_ref()), 219 + 823);
+        assert_eq!(calculate_calibration_value(DATA, Digits::Ascii, line_value_policy("max-pair").unwrap().as_ref()), 29 + 83);
+        assert_eq!(calculate_calibration_value(DATA, Digits::Ascii, line_value_policy("sum").unwrap().as_ref()), 12 + 13);
+        assert!(line_value_policy("median").is_none());
+    }
+
+    #[test]
+    fn digits_unicode_values() {
+        assert_eq!(Digits::Unicode.value('7'), Some(7));
+        assert_eq!(Digits::Unicode.value('３'), Some(3));
+        assert_eq!(Digits::Unicode.value('٤'), Some(4));
+        assert_eq!(Digits::Unicode.value('۹'), Some(9));
+        assert_eq!(Digits::Unicode.value('५'), Some(5));
+        assert_eq!(Digits::Unicode.value('๒'), Some(2));
+        assert_eq!(Digits::Unicode.value('𝟖'), Some(8));
+        assert_eq!(Digits::Unicode.value('a'), None);
+        assert_eq!(Digits::Unicode.value('²'), None);
+        assert_eq!(Digits::Unicode.value('Ⅷ'), None);
+        assert_eq!(Digits::Ascii.value('３'), None);
+    }
+
+    #[test]
+    fn digits_unicode_table_is_numeric() {
+        for zero in UNICODE_ZEROS {
+            for offset in 0..10 {
+                let c = char::from_u32(zero + offset).unwrap();
+                assert!(c.is_numeric(), "c = {:?}", c);
+                assert_eq!(Digits::Unicode.value(c), Some(offset));
+            }
+        }
+    }
+
+    #[test]
+    fn calculate_calibration_value_mixed_scripts() {
+        static DATA: &str = "ab１cd９
+x٣yseven
+५oneight
+two七３";
+        assert_eq!(calculate_calibration_value(DATA, Digits::Unicode, &FirstLast), 19 + 37 + 58 + 23);
+        assert_eq!(calculate_calibration_value_fast(DATA, Digits::Unicode), 19 + 37 + 58 + 23);
+        assert_eq!(scan_line_value("ab１cd９", Digits::Ascii), None);
+        assert_eq!(scan_line_value("x٣yseven", Digits::Ascii), Some(77));
+    }
+
+    fn generate_input(bytes: usize) -> String {
+        static PIECES: [&str; 12] = [ "one", "two", "eight", "nine", "3", "7", "abc", "xyz", "q", "oneight", "twone", "seven" ];
+        let mut input = String::with_capacity(bytes + 64);
+        let mut state: u32 = 12345;
+        while input.len() < bytes {
+            for _ in 0..20 {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                input.push_str(PIECES[(state >> 16) as usize % PIECES.len()]);
+            }
+            input.push('7');
+            input.push('\n');
+        }
+        input
+    }
+
+    #[test]
+    fn scan_line_value_matches_line_parser() {
+        for line in generate_input(64 * 1024).lines() {
+            assert_eq!(scan_line_value(line, Digits::Ascii), Some(line.parse::<Line>().unwrap().value()), "line = {}", line);
+        }
+    }
+
+    // cargo test --release bench_calibration_value -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_calibration_value() {
+        let input = generate_input(8 * 1024 * 1024);
+
+        let start = std::time::Instant::now();
+        let parsed: u32 = input.lines().map(|line| line.parse::<Line>().unwrap().value()).sum();
+        let parsed_elapsed = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let scanned = calculate_calibration_value_fast(&input, Digits::Ascii);
+        let scanned_elapsed = start.elapsed();
+
+        println!("{} bytes: parse = {:?}, scan = {:?}", input.len(), parsed_elapsed, scanned_elapsed);
+        assert_eq!(parsed, scanned);
+    }
 }
@@ -1,111 +1,485 @@
-#[derive(Debug, Clone)]
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+mod inference;
+
+/// Number of cubes per colour, for a single draw or for a whole bag.
+type Cubes = BTreeMap<String, u32>;
+
+#[derive(Debug, Clone, PartialEq)]
 struct Draw {
-    red: u32,
-    green: u32,
-    blue: u32,
+    cubes: Cubes,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Game {
     id: u32,
     draws: Vec<Draw>,
 }
 
-impl std::str::FromStr for Game {
-    type Err = &'static str;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Validation {
+    /// Any problem is an error.
+    Strict,
+    /// Problems that can be worked around become warnings: duplicate colours in a draw
+    /// are added up, zero counts and empty draws are dropped, and unexpected game ids
+    /// are kept. Lines that can't be parsed at all are skipped with a warning.
+    Lenient,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts = s.trim().split_once(':');
-        if parts.is_none() {
-            return Err("Cannot find separator in input")
+#[derive(Debug, Clone, PartialEq)]
+enum ParseErrorKind {
+    MissingSeparator,
+    NotAGame,
+    InvalidId(String),
+    EmptyDraw,
+    /// Every draw of the game was empty or dropped.
+    NoDraws,
+    MissingCount,
+    MissingColor,
+    InvalidCount(String),
+    ZeroCount,
+    DuplicateColor(String),
+    DuplicateId(u32),
+    NonSequentialId { expected: u32, found: u32 },
+}
+
+/// A problem in the input, at a 1-based line and column (in bytes).
+#[derive(Debug, Clone, PartialEq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    fn new(column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError { line: 1, column, kind }
+    }
+
+    fn at_line(self, line: usize) -> ParseError {
+        ParseError { line, ..self }
+    }
+
+    fn is_recoverable(&self) -> bool {
+        matches!(self.kind, ParseErrorKind::EmptyDraw | ParseErrorKind::ZeroCount | ParseErrorKind::DuplicateColor(_) | ParseErrorKind::DuplicateId(_) | ParseErrorKind::NonSequentialId { .. })
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::MissingSeparator => write!(f, "cannot find ':' separator"),
+            ParseErrorKind::NotAGame => write!(f, "expected 'Game ID'"),
+            ParseErrorKind::InvalidId(id) => write!(f, "invalid game id '{}'", id),
+            ParseErrorKind::EmptyDraw => write!(f, "empty draw"),
+            ParseErrorKind::NoDraws => write!(f, "game has no draws"),
+            ParseErrorKind::MissingCount => write!(f, "missing count"),
+            ParseErrorKind::MissingColor => write!(f, "missing color"),
+            ParseErrorKind::InvalidCount(count) => write!(f, "invalid count '{}'", count),
+            ParseErrorKind::ZeroCount => write!(f, "count must not be zero"),
+            ParseErrorKind::DuplicateColor(color) => write!(f, "color '{}' appears twice in the draw", color),
+            ParseErrorKind::DuplicateId(id) => write!(f, "game {} appears twice", id),
+            ParseErrorKind::NonSequentialId { expected, found } => write!(f, "expected game {}, found game {}", expected, found),
         }
-        let id_part = parts.unwrap().0.split_once(' ');
-        if id_part.is_none() {
-            return Err("Cannot find separator in id part")
-        } else if id_part.unwrap().0 != "Game" {
-            return Err("Not a game?")
+    }
+}
+
+/// Reports `error` as a warning when possible, and returns it otherwise.
+fn check(error: ParseError, validation: Validation, warnings: &mut Vec<ParseError>) -> Result<(), ParseError> {
+    if validation == Validation::Lenient && error.is_recoverable() {
+        warnings.push(error);
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+impl Draw {
+    /// `,` separated `NUM COLOR` pairs, `s` starting at `column` of its line. Zero counts
+    /// are kept with `allow_zero`, as bags may hold no cubes of a colour.
+    fn parse(s: &str, column: usize, allow_zero: bool, validation: Validation, warnings: &mut Vec<ParseError>) -> Result<Draw, ParseError> {
+        let mut draw = Draw { cubes: Cubes::new() };
+        if s.trim().is_empty() {
+            check(ParseError::new(column, ParseErrorKind::EmptyDraw), validation, warnings)?;
+            return Ok(draw);
         }
-        let id = id_part.unwrap().1.parse::<u32>().unwrap();
 
-        // Rest is `;` separated instances, and in there `,` separated `NUM COLOR` pairs.
-        let mut draws = vec![];
-        for s in parts.unwrap().1.split(';') {
-            let mut draw = Draw { red: 0, green: 0, blue: 0 };
-            for color_draw in s.split(',') {
-                match color_draw.trim_start().split_once(' ') {
-                    Some((count, "red")) => draw.red = count.parse::<u32>().unwrap(),
-                    Some((count, "green")) => draw.green = count.parse::<u32>().unwrap(),
-                    Some((count, "blue")) => draw.blue = count.parse::<u32>().unwrap(),
-                    _ => {
-                        return Err("Not a known color")
-                    }
-                }
+        let mut offset = 0;
+        for color_draw in s.split(',') {
+            let column = column + offset + (color_draw.len() - color_draw.trim_start().len());
+            offset += color_draw.len() + 1;
+
+            let color_draw = color_draw.trim();
+            let (count, color) = match color_draw.split_once(' ') {
+                Some((count, color)) => (count, color.trim()),
+                None if color_draw.parse::<u32>().is_ok() => return Err(ParseError::new(column, ParseErrorKind::MissingColor)),
+                None => return Err(ParseError::new(column, ParseErrorKind::MissingCount)),
+            };
+            let Ok(count) = count.parse::<u32>() else {
+                return Err(ParseError::new(column, ParseErrorKind::InvalidCount(count.to_string())))
+            };
+            if count == 0 && !allow_zero {
+                check(ParseError::new(column, ParseErrorKind::ZeroCount), validation, warnings)?;
+                continue;
+            }
+            if draw.cubes.contains_key(color) {
+                check(ParseError::new(column, ParseErrorKind::DuplicateColor(color.to_string())), validation, warnings)?;
             }
+            *draw.cubes.entry(color.to_string()).or_insert(0) += count;
+        }
+        Ok(draw)
+    }
+}
 
-            draws.push(draw)
+impl Game {
+    fn parse(s: &str, validation: Validation, warnings: &mut Vec<ParseError>) -> Result<Game, ParseError> {
+        let Some((id_part, draws_part)) = s.split_once(':') else {
+            return Err(ParseError::new(1, ParseErrorKind::MissingSeparator))
+        };
+        let leading = id_part.len() - id_part.trim_start().len();
+        let Some(id) = id_part.trim().strip_prefix("Game ") else {
+            return Err(ParseError::new(leading + 1, ParseErrorKind::NotAGame))
+        };
+        let Ok(id) = id.trim().parse::<u32>() else {
+            return Err(ParseError::new(leading + "Game ".len() + 1, ParseErrorKind::InvalidId(id.trim().to_string())))
+        };
+
+        // Rest is `;` separated instances.
+        let mut draws = vec![];
+        let mut column = id_part.len() + 2;
+        for s in draws_part.split(';') {
+            let draw = Draw::parse(s, column, false, validation, warnings)?;
+            if !draw.cubes.is_empty() {
+                draws.push(draw);
+            }
+            column += s.len() + 1;
+        }
+        if draws.is_empty() {
+            return Err(ParseError::new(id_part.len() + 2, ParseErrorKind::NoDraws));
         }
 
         Ok(Game { id, draws })
     }
 }
 
+impl std::str::FromStr for Draw {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Draw::parse(s, 1, false, Validation::Strict, &mut vec![])
+    }
+}
+
+impl std::str::FromStr for Game {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Game::parse(s, Validation::Strict, &mut vec![])
+    }
+}
+
+impl std::fmt::Display for Draw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, (color, count)) in self.cubes.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", count, color)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Game {}:", self.id)?;
+        for (index, draw) in self.draws.iter().enumerate() {
+            write!(f, "{} {}", if index > 0 { ";" } else { "" }, draw)?;
+        }
+        Ok(())
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// All games as a JSON array of `{"id": N, "draws": [{"COLOR": COUNT, ...}, ...]}` objects.
+fn games_to_json(games: &[Game]) -> String {
+    let games: Vec<String> = games.iter().map(|game| {
+        let draws: Vec<String> = game.draws.iter().map(|draw| {
+            let cubes: Vec<String> = draw.cubes.iter().map(|(color, count)| format!("{}: {}", json_string(color), count)).collect();
+            format!("{{{}}}", cubes.join(", "))
+        }).collect();
+        format!("  {{\"id\": {}, \"draws\": [{}]}}", game.id, draws.join(", "))
+    }).collect();
+    format!("[\n{}\n]", games.join(",\n"))
+}
+
+/// A colour in a draw that needed more cubes than the bag holds.
+#[derive(Debug, PartialEq)]
+struct Violation {
+    draw: usize,
+    color: String,
+    drawn: u32,
+    available: u32,
+}
+
+impl Violation {
+    fn excess(&self) -> u32 {
+        self.drawn - self.available
+    }
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "draw {}: {} {} drawn, bag has {} ({} too many)", self.draw, self.drawn, self.color, self.available, self.excess())
+    }
+}
+
 impl Game {
-    pub fn is_possible(&self, red: u32, green: u32, blue: u32) -> bool {
+    pub fn is_possible(&self, bag: &Cubes) -> bool {
+        self.violations(bag).is_empty()
+    }
+
+    /// Every draw and colour that exceeds `bag`, draws counted from 0.
+    pub fn violations(&self, bag: &Cubes) -> Vec<Violation> {
+        let mut violations = vec![];
+        for (index, draw) in self.draws.iter().enumerate() {
+            for (color, count) in draw.cubes.iter() {
+                let available = bag.get(color).copied().unwrap_or(0);
+                if *count > available {
+                    violations.push(Violation { draw: index, color: color.clone(), drawn: *count, available });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Smallest bag for which this game is possible.
+    pub fn minimal_bag(&self) -> Cubes {
+        let mut bag = Cubes::new();
         for draw in self.draws.iter() {
-            if draw.red > red || draw.green > green || draw.blue > blue {
-                return false
+            for (color, count) in draw.cubes.iter() {
+                let max = bag.entry(color.clone()).or_insert(0);
+                *max = std::cmp::max(*max, *count);
             }
         }
-        true
+        bag
     }
 }
 
-fn sum_of_possible_games(input: &str) -> u32 {
-    let mut result = 0;
-    for line in input.lines() {
-        match line.parse::<Game>() {
-            Ok(game) => {
-                if game.is_possible(12, 13, 14) {
-                    result += game.id;
-                }
+fn parse_games(input: &str) -> Vec<Game> {
+    let (games, warnings) = parse_games_with(input, Validation::Lenient).unwrap();
+    for warning in warnings {
+        println!("warning = {}", warning);
+    }
+    games
+}
+
+/// Parses all games, also checking that game ids count up from 1. Returns the games
+/// and the warnings, or the first error.
+fn parse_games_with(input: &str, validation: Validation) -> Result<(Vec<Game>, Vec<ParseError>), ParseError> {
+    let mut games = vec![];
+    let mut warnings = vec![];
+    let mut ids = HashSet::new();
+    for (index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut line_warnings = vec![];
+        let game = match Game::parse(line, validation, &mut line_warnings) {
+            Ok(game) => game,
+            Err(error) if validation == Validation::Lenient => {
+                warnings.push(error.at_line(index + 1));
+                continue;
             },
-            Err(reason) => println!("error = {}", reason)
+            Err(error) => return Err(error.at_line(index + 1)),
+        };
+        warnings.extend(line_warnings.into_iter().map(|warning| warning.at_line(index + 1)));
+
+        let column = line.find(|c: char| c.is_ascii_digit()).unwrap_or(0) + 1;
+        let expected = games.last().map_or(1, |game: &Game| game.id + 1);
+        if !ids.insert(game.id) {
+            check(ParseError { line: index + 1, column, kind: ParseErrorKind::DuplicateId(game.id) }, validation, &mut warnings)?;
+        } else if game.id != expected {
+            check(ParseError { line: index + 1, column, kind: ParseErrorKind::NonSequentialId { expected, found: game.id } }, validation, &mut warnings)?;
+        }
+        games.push(game);
+    }
+    Ok((games, warnings))
+}
+
+fn puzzle_bag() -> Cubes {
+    Cubes::from([("red".to_string(), 12), ("green".to_string(), 13), ("blue".to_string(), 14)])
+}
+
+fn sum_of_possible_games(input: &str) -> u32 {
+    sums_of_possible_games(&parse_games(input), &[puzzle_bag()])[0]
+}
+
+/// Sum of the ids of the possible games for each of `bags`, in one pass over the games.
+fn sums_of_possible_games(games: &[Game], bags: &[Cubes]) -> Vec<u32> {
+    let mut result = vec![0; bags.len()];
+    for game in games {
+        for (sum, bag) in result.iter_mut().zip(bags.iter()) {
+            if game.is_possible(bag) {
+                *sum += game.id;
+            }
         }
     }
     result
 }
 
-fn sum_of_minimal_powers(input: &str) -> u32 {
+/// Smallest bag for which every game is possible.
+fn minimal_bag_for_all(games: &[Game]) -> Cubes {
+    let mut bag = Cubes::new();
+    for game in games {
+        for (color, count) in game.minimal_bag() {
+            let max = bag.entry(color).or_insert(0);
+            *max = std::cmp::max(*max, count);
+        }
+    }
+    bag
+}
+
+/// Bags written like draws (`12 red, 13 green, 14 blue`), one per line.
+fn parse_bags(input: &str) -> Result<Vec<Cubes>, ParseError> {
+    input.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| Draw::parse(line, 1, true, Validation::Strict, &mut vec![]).map(|draw| draw.cubes).map_err(|error| error.at_line(index + 1)))
+        .collect()
+}
+
+/// Sum of the possible game ids, and the rejected games with the reasons.
+fn possible_games_report(input: &str) -> (u32, Vec<(u32, Vec<Violation>)>) {
+    let bag = puzzle_bag();
     let mut result = 0;
-    for line in input.lines() {
-        match line.parse::<Game>() {
-            Ok(game) => {
-                let mut max_red = 0;
-                let mut max_green = 0;
-                let mut max_blue = 0;
-
-                for draw in game.draws {
-                    if draw.red > 0 {
-                        max_red = std::cmp::max(max_red, draw.red);
-                    }
-                    if draw.green > 0 {
-                        max_green = std::cmp::max(max_green, draw.green);
-                    }
-                    if draw.blue > 0 {
-                        max_blue = std::cmp::max(max_blue, draw.blue)
-                    }
-                }
-                result += max_red * max_green * max_blue;
-            },
-            Err(reason) => println!("error = {}", reason)
+    let mut rejected = vec![];
+    for game in parse_games(input) {
+        let violations = game.violations(&bag);
+        if violations.is_empty() {
+            result += game.id;
+        } else {
+            rejected.push((game.id, violations));
         }
     }
-    result
+    (result, rejected)
+}
+
+fn sum_of_minimal_powers(input: &str) -> Result<u128, &'static str> {
+    let games = parse_games(input);
+
+    // A colour that only other games draw still counts for this game, with zero cubes.
+    let colors: BTreeSet<&String> = games.iter()
+        .flat_map(|game| game.draws.iter())
+        .flat_map(|draw| draw.cubes.keys())
+        .collect();
+
+    let mut result: u128 = 0;
+    for game in games.iter() {
+        let bag = game.minimal_bag();
+        let power = colors.iter()
+            .map(|color| bag.get(*color).copied().unwrap_or(0) as u128)
+            .try_fold(1_u128, |power, count| power.checked_mul(count))
+            .ok_or("power too large")?;
+        result = result.checked_add(power).ok_or("power too large")?;
+    }
+    Ok(result)
 }
 
 pub fn main() {
+    let report = std::env::args().any(|arg| arg == "--report");
+    let json = std::env::args().any(|arg| arg == "--json");
+    let normalise = std::env::args().any(|arg| arg == "--normalise");
+    let infer = std::env::args().any(|arg| arg == "--infer");
+    let strict = std::env::args().any(|arg| arg == "--strict");
+
+    // Bags to evaluate: `--bag=12 red, 13 green, 14 blue` (repeatable) and/or `--bags=FILE`.
+    let mut bags = vec![];
+    for arg in std::env::args().skip(1) {
+        let parsed = if let Some(bag) = arg.strip_prefix("--bag=") {
+            parse_bags(bag)
+        } else if let Some(path) = arg.strip_prefix("--bags=") {
+            match std::fs::read_to_string(path) {
+                Ok(content) => parse_bags(&content),
+                Err(reason) => { println!("error = {}", reason); return }
+            }
+        } else {
+            continue
+        };
+        match parsed {
+            Ok(parsed) => bags.extend(parsed),
+            Err(reason) => { println!("error = {} in {}", reason, arg); return }
+        }
+    }
+
     match std::fs::read_to_string("day2.input") {
-        Ok(input) => println!("part1 = {}, part2 = {}", sum_of_possible_games(&input), sum_of_minimal_powers(&input)),
+        Ok(input) => {
+            if strict {
+                if let Err(error) = parse_games_with(&input, Validation::Strict) {
+                    println!("error = {}", error);
+                    return
+                }
+            }
+            if !bags.is_empty() {
+                let games = parse_games(&input);
+                for (bag, sum) in bags.iter().zip(sums_of_possible_games(&games, &bags)) {
+                    println!("{:<40} {}", Draw { cubes: bag.clone() }.to_string(), sum);
+                }
+                println!("minimal bag = {}", Draw { cubes: minimal_bag_for_all(&games) });
+                return
+            }
+            if json {
+                println!("{}", games_to_json(&parse_games(&input)));
+                return
+            }
+            if infer {
+                for game in parse_games(&input) {
+                    let estimate = inference::estimate_bag(&game, 30);
+                    println!("game {}: bag = {:?}, log-likelihood = {}, intervals = {:?}", game.id, estimate.bag, estimate.log_likelihood, estimate.intervals);
+                    if estimate.truncated {
+                        println!("game {}: the most likely bag is at the search limit, a bigger one may be more likely", game.id);
+                    }
+                    for (bag, ll) in inference::rank_bags(&game, &[puzzle_bag(), game.minimal_bag()]) {
+                        println!("  bag = {:?}, log-likelihood = {}", bag, ll);
+                    }
+                }
+                return
+            }
+            if normalise {
+                for game in parse_games(&input) {
+                    println!("{}", game);
+                }
+                return
+            }
+            if report {
+                let (_, rejected) = possible_games_report(&input);
+                for (id, violations) in rejected {
+                    println!("game {} rejected:", id);
+                    for violation in violations {
+                        println!("  {}", violation);
+                    }
+                }
+            }
+            match sum_of_minimal_powers(&input) {
+                Ok(part2) => println!("part1 = {}, part2 = {}", sum_of_possible_games(&input), part2),
+                Err(reason) => println!("part1 = {}, part2 error = {}", sum_of_possible_games(&input), reason),
+            }
+        },
         Err(reason) => println!("error = {}", reason)
     }
 }
@@ -114,25 +488,185 @@ pub fn main() {
 mod tests {
     use super::*;
 
-    #[test]
-    fn sum_of_possible_games_example1() {
-        static DATA: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
+    static DATA: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
 Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
 Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
 Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
 Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
 
+    #[test]
+    fn sum_of_possible_games_example1() {
         assert_eq!(sum_of_possible_games(DATA), 8);
     }
 
     #[test]
     fn sum_of_minimal_powers_example2() {
-        static DATA: &str = "Game 1: 3 blue, 4 red; 1 red, 2 green, 6 blue; 2 green
-Game 2: 1 blue, 2 green; 3 green, 4 blue, 1 red; 1 green, 1 blue
-Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red
-Game 4: 1 green, 3 red, 6 blue; 3 green, 6 red; 3 green, 15 blue, 14 red
-Game 5: 6 red, 1 blue, 3 green; 2 blue, 1 red, 2 green";
+        assert_eq!(sum_of_minimal_powers(DATA), Ok(2286));
+    }
 
-        assert_eq!(sum_of_minimal_powers(DATA), 2286);
+    #[test]
+    fn is_possible_other_colors() {
+        let game = "Game 7: 2 yellow, 1 red; 3 purple".parse::<Game>().unwrap();
+        let bag = Cubes::from([("red".to_string(), 1), ("yellow".to_string(), 2), ("purple".to_string(), 3)]);
+        assert!(game.is_possible(&bag));
+
+        let bag = Cubes::from([("red".to_string(), 1), ("yellow".to_string(), 2)]);
+        assert!(!game.is_possible(&bag));
+    }
+
+    #[test]
+    fn violations_example() {
+        let game = "Game 3: 8 green, 6 blue, 20 red; 5 blue, 4 red, 13 green; 5 green, 1 red".parse::<Game>().unwrap();
+        let bag = puzzle_bag();
+        let violations = game.violations(&bag);
+        assert_eq!(violations, vec![Violation { draw: 0, color: "red".to_string(), drawn: 20, available: 12 }]);
+        assert_eq!(violations[0].excess(), 8);
+        assert_eq!(violations[0].to_string(), "draw 0: 20 red drawn, bag has 12 (8 too many)");
+    }
+
+    #[test]
+    fn possible_games_report_example() {
+        let (sum, rejected) = possible_games_report(DATA);
+        assert_eq!(sum, 8);
+        assert_eq!(rejected.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(rejected[1].1, vec![
+            Violation { draw: 2, color: "blue".to_string(), drawn: 15, available: 14 },
+            Violation { draw: 2, color: "red".to_string(), drawn: 14, available: 12 },
+        ]);
+    }
+
+    #[test]
+    fn display_canonical() {
+        let game = "Game 1: 4 red, 3 blue;1 red,  2 green, 6 blue; 2 green".parse::<Game>().unwrap();
+        assert_eq!(game.to_string(), "Game 1: 3 blue, 4 red; 6 blue, 2 green, 1 red; 2 green");
+        assert_eq!(game.draws[2].to_string(), "2 green");
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let games = parse_games(&format!("{}\nGame 6: 7 yellow; 1 purple, 2 yellow\nGame 7: 0 red;", DATA));
+        assert_eq!(games.len(), 6);
+        for game in games {
+            let displayed = game.to_string();
+            let parsed = displayed.parse::<Game>().unwrap();
+            assert_eq!(parsed, game);
+            assert_eq!(parsed.to_string(), displayed);
+        }
+    }
+
+    #[test]
+    fn games_to_json_example() {
+        let games = parse_games("Game 1: 3 blue, 4 red; 2 green
+Game 2: 1 blue");
+        assert_eq!(games_to_json(&games), r#"[
+  {"id": 1, "draws": [{"blue": 3, "red": 4}, {"green": 2}]},
+  {"id": 2, "draws": [{"blue": 1}]}
+]"#);
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("red"), r#""red""#);
+        assert_eq!(json_string("a\"b\\c\n"), r#""a\"b\\c\u000a""#);
+    }
+
+    #[test]
+    fn sums_of_possible_games_many_bags() {
+        let games = parse_games(DATA);
+        let bags = parse_bags("12 red, 13 green, 14 blue
+20 red, 13 green, 15 blue
+
+1 red, 1 blue").unwrap();
+        assert_eq!(sums_of_possible_games(&games, &bags), vec![8, 15, 0]);
+
+        let minimal = minimal_bag_for_all(&games);
+        assert_eq!(Draw { cubes: minimal.clone() }.to_string(), "15 blue, 13 green, 20 red");
+        assert_eq!(sums_of_possible_games(&games, &[minimal]), vec![15]);
+    }
+
+    #[test]
+    fn parse_bags_invalid() {
+        assert!(parse_bags("12 red, blue").is_err());
+        assert_eq!(parse_bags("1 red, 2 red").unwrap_err().kind, ParseErrorKind::DuplicateColor("red".to_string()));
+    }
+
+    #[test]
+    fn parse_bags_zero_count() {
+        let bags = parse_bags("0 red, 13 green, 14 blue").unwrap();
+        assert_eq!(bags, vec![Cubes::from([("red".to_string(), 0), ("green".to_string(), 13), ("blue".to_string(), 14)])]);
+        assert_eq!(Draw { cubes: bags[0].clone() }.to_string(), "14 blue, 13 green, 0 red");
+        let games = parse_games("Game 1: 3 green\nGame 2: 1 red, 2 blue");
+        assert_eq!(sums_of_possible_games(&games, &bags), vec![1]);
+    }
+
+    #[test]
+    fn parse_errors_positioned() {
+        fn error(line: &str) -> (usize, ParseErrorKind) {
+            let error = line.parse::<Game>().unwrap_err();
+            (error.column, error.kind)
+        }
+        assert_eq!(error("Game 1 3 blue"), (1, ParseErrorKind::MissingSeparator));
+        assert_eq!(error("Gme 1: 3 blue"), (1, ParseErrorKind::NotAGame));
+        assert_eq!(error("Game x1: 3 blue"), (6, ParseErrorKind::InvalidId("x1".to_string())));
+        assert_eq!(error("Game 1: 3 blue;; 2 red"), (16, ParseErrorKind::EmptyDraw));
+        assert_eq!(error("Game 1:"), (8, ParseErrorKind::EmptyDraw));
+        assert_eq!(error("Game 1: 3 blue, red"), (17, ParseErrorKind::MissingCount));
+        assert_eq!(error("Game 1: 3 blue, 4"), (17, ParseErrorKind::MissingColor));
+        assert_eq!(error("Game 1: 3 blue; 4x red"), (17, ParseErrorKind::InvalidCount("4x".to_string())));
+        assert_eq!(error("Game 1: 3 blue; 99999999999 red"), (17, ParseErrorKind::InvalidCount("99999999999".to_string())));
+        assert_eq!(error("Game 1: 3 blue, 0 red"), (17, ParseErrorKind::ZeroCount));
+        assert_eq!(error("Game 1: 3 blue; 1 red, 2 blue, 4 red"), (32, ParseErrorKind::DuplicateColor("red".to_string())));
+    }
+
+    #[test]
+    fn parse_games_strict() {
+        let error = parse_games_with("Game 1: 3 blue
+Game 2: 1 red
+Game 2: 1 green", Validation::Strict).unwrap_err();
+        assert_eq!(error, ParseError { line: 3, column: 6, kind: ParseErrorKind::DuplicateId(2) });
+        assert_eq!(error.to_string(), "line 3, column 6: game 2 appears twice");
+
+        let error = parse_games_with("Game 1: 3 blue
+Game 3: 1 red", Validation::Strict).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::NonSequentialId { expected: 2, found: 3 });
+
+        let error = parse_games_with("Game 1: 3 blue
+Game 2: 1 red, 1 blue; ", Validation::Strict).unwrap_err();
+        assert_eq!((error.line, error.column, error.kind), (2, 23, ParseErrorKind::EmptyDraw));
+    }
+
+    #[test]
+    fn parse_games_lenient() {
+        let (games, warnings) = parse_games_with("Game 1: 3 blue, 2 blue; ; 0 red, 1 green
+Game x: 1 red
+Game 3: 1 red
+Game 3: 2 red", Validation::Lenient).unwrap();
+        assert_eq!(games.iter().map(|game| game.to_string()).collect::<Vec<_>>(), vec![
+            "Game 1: 5 blue; 1 green",
+            "Game 3: 1 red",
+            "Game 3: 2 red",
+        ]);
+        assert_eq!(warnings.iter().map(|warning| (warning.line, warning.column)).collect::<Vec<_>>(), vec![
+            (1, 17), (1, 24), (1, 27), (2, 6), (3, 6), (4, 6),
+        ]);
+
+        let (games, warnings) = parse_games_with("Game 1: 0 red;", Validation::Lenient).unwrap();
+        assert!(games.is_empty());
+        assert_eq!(warnings.last().map(|warning| (warning.column, warning.kind.clone())), Some((8, ParseErrorKind::NoDraws)));
+    }
+
+    #[test]
+    fn sum_of_minimal_powers_discovered_colors() {
+        static DATA: &str = "Game 1: 2 yellow, 1 red; 3 purple, 4 red
+Game 2: 5 yellow; 2 red, 1 purple, 2 yellow
+Game 3: 1 red";
+        assert_eq!(sum_of_minimal_powers(DATA), Ok(24 + 10));
+    }
+
+    #[test]
+    fn sum_of_minimal_powers_large() {
+        assert_eq!(sum_of_minimal_powers("Game 1: 100000 red, 100000 blue, 100000 green"), Ok(1_000_000_000_000_000));
+        let many_colors: Vec<String> = (0..10).map(|i| format!("4000000000 c{}", i)).collect();
+        assert_eq!(sum_of_minimal_powers(&format!("Game 1: {}", many_colors.join(", "))), Err("power too large"));
     }
 }
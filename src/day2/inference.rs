@@ -0,0 +1,242 @@
+//! Estimating the bag behind a game from its draws.
+//!
+//! A draw takes a handful of cubes out of the bag, and the cubes are put back
+//! before the next draw. Within a draw that is sampling without replacement, so
+//! each draw follows a multivariate hypergeometric distribution, and the draws of
+//! a game are independent of each other.
+//!
+//! Only colours that the game actually draws are estimated: a colour that never
+//! shows up could be missing from the bag or just unlucky, the draws can't tell.
+
+use std::collections::BTreeMap;
+
+use super::{Cubes, Draw, Game};
+
+/// Half the 95% quantile of the chi-squared distribution with one degree of freedom,
+/// for likelihood-ratio intervals.
+const HALF_CHI2_95: f64 = 1.920729;
+
+fn ln_binomial(n: u32, k: u32) -> f64 {
+    if k > n {
+        return f64::NEG_INFINITY;
+    }
+    let k = std::cmp::min(k, n - k);
+    (0..k).map(|i| ((n - i) as f64).ln() - ((i + 1) as f64).ln()).sum()
+}
+
+fn draw_log_likelihood(draw: &Draw, bag: &Cubes) -> f64 {
+    if draw.cubes.keys().any(|color| !bag.contains_key(color)) {
+        return f64::NEG_INFINITY;
+    }
+    let mut result = 0.0;
+    let mut total = 0;
+    let mut drawn = 0;
+    for (color, count) in bag.iter() {
+        let k = draw.cubes.get(color).copied().unwrap_or(0);
+        if k > *count {
+            return f64::NEG_INFINITY;
+        }
+        result += ln_binomial(*count, k);
+        total += count;
+        drawn += k;
+    }
+    result - ln_binomial(total, drawn)
+}
+
+/// Log-likelihood of `bag` producing the draws of `game`, `-inf` if it can't.
+pub fn log_likelihood(game: &Game, bag: &Cubes) -> f64 {
+    game.draws.iter().map(|draw| draw_log_likelihood(draw, bag)).sum()
+}
+
+#[derive(Debug)]
+pub struct Estimate {
+    /// The most likely bag.
+    pub bag: Cubes,
+    pub log_likelihood: f64,
+    /// 95% likelihood-ratio interval for the count of each colour. The upper end is
+    /// `None` if the interval reaches the search limit and might go on beyond it.
+    pub intervals: BTreeMap<String, (u32, Option<u32>)>,
+    /// Whether the most likely bag has a colour at the search limit, so a bigger bag
+    /// might be more likely still.
+    pub truncated: bool,
+}
+
+/// Most likely bag for `game`, trying every bag that has at least as many cubes of
+/// each colour as the game needs and at most `max_per_color`.
+///
+/// This is a full grid search, so the cost grows with `max_per_color` to the power
+/// of the number of colours.
+pub fn estimate_bag(game: &Game, max_per_color: u32) -> Estimate {
+    let minimal = game.minimal_bag();
+    let colors: Vec<&String> = minimal.keys().collect();
+    let lower: Vec<u32> = minimal.values().copied().collect();
+    let upper: Vec<u32> = lower.iter().map(|min| std::cmp::max(*min, max_per_color)).collect();
+
+    // Best log-likelihood seen for every value of every colour (the profile likelihood).
+    let mut profiles: Vec<Vec<f64>> = lower.iter().zip(upper.iter())
+        .map(|(min, max)| vec![f64::NEG_INFINITY; (max - min + 1) as usize])
+        .collect();
+    let mut best = (minimal.clone(), f64::NEG_INFINITY);
+
+    let mut counts = lower.clone();
+    loop {
+        let bag: Cubes = colors.iter().zip(counts.iter()).map(|(color, count)| ((*color).clone(), *count)).collect();
+        let ll = log_likelihood(game, &bag);
+        for (i, count) in counts.iter().enumerate() {
+            let profile = &mut profiles[i][(count - lower[i]) as usize];
+            *profile = profile.max(ll);
+        }
+        if ll > best.1 {
+            best = (bag, ll);
+        }
+
+        // Next combination, odometer style.
+        let mut i = 0;
+        while i < counts.len() && counts[i] == upper[i] {
+            counts[i] = lower[i];
+            i += 1;
+        }
+        if i == counts.len() {
+            break;
+        }
+        counts[i] += 1;
+    }
+
+    let mut intervals = BTreeMap::new();
+    for (i, color) in colors.iter().enumerate() {
+        let inside: Vec<u32> = profiles[i].iter().enumerate()
+            .filter(|(_, ll)| **ll >= best.1 - HALF_CHI2_95)
+            .map(|(offset, _)| lower[i] + offset as u32)
+            .collect();
+        let high = inside[inside.len() - 1];
+        intervals.insert((*color).clone(), (inside[0], if high == upper[i] { None } else { Some(high) }));
+    }
+
+    let truncated = colors.iter().zip(upper.iter()).any(|(color, max)| best.0[*color] == *max);
+    Estimate { bag: best.0, log_likelihood: best.1, intervals, truncated }
+}
+
+/// `candidates` with their log-likelihood for `game`, most likely first.
+pub fn rank_bags(game: &Game, candidates: &[Cubes]) -> Vec<(Cubes, f64)> {
+    let mut ranked: Vec<(Cubes, f64)> = candidates.iter().map(|bag| (bag.clone(), log_likelihood(game, bag))).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64*, good enough to shuffle cubes reproducibly.
+    struct Rng(u64);
+
+    impl Rng {
+        fn new(seed: u64) -> Rng {
+            Rng(seed.max(1))
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as usize % n
+        }
+    }
+
+    /// A game of `draws` handfuls of `handful` cubes each taken from `bag`.
+    fn simulate_game(id: u32, bag: &Cubes, draws: usize, handful: usize, rng: &mut Rng) -> Game {
+        let cubes: Vec<&String> = bag.iter().flat_map(|(color, count)| std::iter::repeat_n(color, *count as usize)).collect();
+        let handful = std::cmp::min(handful, cubes.len());
+        let mut result = vec![];
+        for _ in 0..draws {
+            // Partial Fisher-Yates shuffle of the bag for the handful.
+            let mut order: Vec<usize> = (0..cubes.len()).collect();
+            let mut draw = Draw { cubes: Cubes::new() };
+            for i in 0..handful {
+                let j = i + rng.below(order.len() - i);
+                order.swap(i, j);
+                *draw.cubes.entry(cubes[order[i]].clone()).or_insert(0) += 1;
+            }
+            result.push(draw);
+        }
+        Game { id, draws: result }
+    }
+
+    fn bag(cubes: &[(&str, u32)]) -> Cubes {
+        cubes.iter().map(|(color, count)| (color.to_string(), *count)).collect()
+    }
+
+    #[test]
+    fn ln_binomial_values() {
+        assert!((ln_binomial(5, 2) - 10_f64.ln()).abs() < 1e-9);
+        assert_eq!(ln_binomial(5, 0), 0.0);
+        assert_eq!(ln_binomial(2, 3), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn log_likelihood_single_draw() {
+        let game = "Game 1: 1 red, 1 blue".parse::<Game>().unwrap();
+        // One of each from 2 red and 2 blue: 2 * 2 of the 6 possible handfuls.
+        let ll = log_likelihood(&game, &bag(&[("red", 2), ("blue", 2)]));
+        assert!((ll - (4.0_f64 / 6.0).ln()).abs() < 1e-9);
+        assert_eq!(log_likelihood(&game, &bag(&[("red", 2)])), f64::NEG_INFINITY);
+        assert_eq!(log_likelihood(&game, &bag(&[("red", 0), ("blue", 2)])), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn simulate_game_is_seeded() {
+        let truth = bag(&[("red", 5), ("green", 3), ("blue", 8)]);
+        let a = simulate_game(1, &truth, 10, 6, &mut Rng::new(42));
+        let b = simulate_game(1, &truth, 10, 6, &mut Rng::new(42));
+        assert_eq!(a, b);
+        assert!(a.draws.iter().all(|draw| draw.cubes.values().sum::<u32>() == 6));
+        assert!(a.is_possible(&truth));
+    }
+
+    #[test]
+    fn estimate_bag_simulated() {
+        let truth = bag(&[("red", 5), ("green", 3), ("blue", 8)]);
+        let game = simulate_game(1, &truth, 300, 8, &mut Rng::new(2023));
+        let estimate = estimate_bag(&game, 14);
+        for (color, count) in truth.iter() {
+            let (low, high) = estimate.intervals[color];
+            let high = high.unwrap();
+            assert!(low <= *count && *count <= high, "color = {}, interval = {:?}, estimate = {:?}", color, (low, high), estimate.bag);
+            assert!(low <= estimate.bag[color] && estimate.bag[color] <= high);
+        }
+        assert!(!estimate.truncated);
+    }
+
+    #[test]
+    fn estimate_bag_truncated() {
+        // Drawing two of a colour gets more likely the more cubes there are, all the way up.
+        let game = "Game 1: 2 red; 2 blue".parse::<Game>().unwrap();
+        let estimate = estimate_bag(&game, 10);
+        assert!(estimate.truncated);
+        assert_eq!(estimate.bag, bag(&[("red", 10), ("blue", 10)]));
+        assert_eq!(estimate.intervals["red"].1, None);
+
+        // A single colour fits any bag equally well: the estimate isn't at the limit,
+        // but the interval is.
+        let game = "Game 1: 3 red".parse::<Game>().unwrap();
+        let estimate = estimate_bag(&game, 10);
+        assert!(!estimate.truncated);
+        assert_eq!(estimate.bag, bag(&[("red", 3)]));
+        assert_eq!(estimate.intervals["red"], (3, None));
+    }
+
+    #[test]
+    fn rank_bags_prefers_truth() {
+        let truth = bag(&[("red", 4), ("blue", 12)]);
+        let game = simulate_game(1, &truth, 200, 6, &mut Rng::new(7));
+        let candidates = [
+            bag(&[("red", 12), ("blue", 4)]),
+            bag(&[("red", 4), ("blue", 12)]),
+            bag(&[("red", 1), ("blue", 1)]),
+            bag(&[("red", 8), ("blue", 8)]),
+        ];
+        let ranked = rank_bags(&game, &candidates);
+        assert_eq!(ranked[0].0, truth);
+        assert_eq!(ranked[3].1, f64::NEG_INFINITY);
+    }
+}
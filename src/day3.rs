@@ -1,17 +1,25 @@
-use std::str::from_utf8;
+use std::collections::HashMap;
+use std::io::BufRead;
 
 struct Schematic {
     width: usize,
     lines: Vec<Vec<u8>>,
+    parts: Vec<Part>,
+    symbols: Vec<Symbol>,
+    /// Symbols (indices into `symbols`) next to each part (index into `parts`).
+    part_symbols: HashMap<usize, Vec<usize>>,
+    /// Parts (indices into `parts`) next to each symbol (index into `symbols`).
+    symbol_parts: HashMap<usize, Vec<usize>>,
+    symbol_at: HashMap<Pos, usize>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 struct Pos {
     y: usize,
     x: usize,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 struct Symbol {
     pos: Pos,
     symbol: u8,
@@ -19,28 +27,217 @@ struct Symbol {
 
 type PartNumber = u32;
 
+/// Sums of part numbers and gear ratios. Two part numbers always multiply into a u128,
+/// and there can't be enough of them in a schematic to overflow the sum.
+type Total = u128;
+
+/// A number in the schematic, starting at `pos` and `len` digits long.
+#[derive(Debug, PartialEq, Clone)]
+struct Part {
+    pos: Pos,
+    len: usize,
+    number: PartNumber,
+}
+
+/// How many parts a symbol needs around it for a rule.
+#[derive(Debug, Clone)]
+enum PartCount {
+    Exactly(usize),
+    AtLeast(usize),
+    /// Inclusive on both ends.
+    Between(usize, usize),
+}
+
+impl PartCount {
+    fn matches(&self, count: usize) -> bool {
+        match self {
+            PartCount::Exactly(n) => count == *n,
+            PartCount::AtLeast(n) => count >= *n,
+            PartCount::Between(min, max) => *min <= count && count <= *max,
+        }
+    }
+}
+
+/// How the part numbers around a symbol are combined.
+#[derive(Debug, Clone)]
+enum Aggregation {
+    Product,
+    Sum,
+    Max,
+}
+
+impl Aggregation {
+    /// `None` if the result doesn't fit into a `Total`.
+    fn apply(&self, numbers: impl Iterator<Item = PartNumber>) -> Option<Total> {
+        let mut numbers = numbers.map(|number| number as Total);
+        match self {
+            Aggregation::Product => numbers.try_fold(1, |product: Total, number| product.checked_mul(number)),
+            Aggregation::Sum => numbers.try_fold(0, |sum: Total, number| sum.checked_add(number)),
+            Aggregation::Max => Some(numbers.max().unwrap_or(0)),
+        }
+    }
+}
+
+/// Which symbols count as gears, and what they are worth.
+#[derive(Debug, Clone)]
+struct GearRule {
+    /// `None` for any symbol.
+    symbols: Option<Vec<u8>>,
+    parts: PartCount,
+    aggregation: Aggregation,
+}
+
+impl GearRule {
+    /// The puzzle's rule: `*` with exactly two parts, worth their product.
+    fn gear() -> GearRule {
+        GearRule { symbols: Some(vec![b'*']), parts: PartCount::Exactly(2), aggregation: Aggregation::Product }
+    }
+
+    fn applies_to(&self, symbol: u8) -> bool {
+        self.symbols.as_ref().is_none_or(|symbols| symbols.contains(&symbol))
+    }
+}
+
+impl std::str::FromStr for GearRule {
+    type Err = String;
+
+    /// `SYMBOLS:COUNT:AGGREGATION`, for example `*:2:product`, `any:3+:sum` or `#$:2-4:max`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [symbols, count, aggregation] = s.splitn(3, ':').collect::<Vec<_>>()[..] else {
+            return Err(format!("expected SYMBOLS:COUNT:AGGREGATION, found '{}'", s))
+        };
+        let symbols = if symbols == "any" { None } else { Some(symbols.as_bytes().to_vec()) };
+        let parse_count = |n: &str| n.parse::<usize>().map_err(|_| format!("invalid part count '{}'", n));
+        let parts = if let Some(min) = count.strip_suffix('+') {
+            PartCount::AtLeast(parse_count(min)?)
+        } else if let Some((min, max)) = count.split_once('-') {
+            PartCount::Between(parse_count(min)?, parse_count(max)?)
+        } else {
+            PartCount::Exactly(parse_count(count)?)
+        };
+        let aggregation = match aggregation {
+            "product" => Aggregation::Product,
+            "sum" => Aggregation::Sum,
+            "max" => Aggregation::Max,
+            _ => return Err(format!("unknown aggregation '{}'", aggregation)),
+        };
+        Ok(GearRule { symbols, parts, aggregation })
+    }
+}
+
+/// Connected parts with their symbols (indices into the schematic's `parts` and `symbols`),
+/// and the inclusive bounding box around all of them.
+#[derive(Debug, PartialEq)]
+struct Cluster {
+    parts: Vec<usize>,
+    symbols: Vec<usize>,
+    top_left: Pos,
+    bottom_right: Pos,
+}
+
+impl Cluster {
+    fn extend(&mut self, pos: Pos) {
+        self.top_left = Pos { y: std::cmp::min(self.top_left.y, pos.y), x: std::cmp::min(self.top_left.x, pos.x) };
+        self.bottom_right = Pos { y: std::cmp::max(self.bottom_right.y, pos.y), x: std::cmp::max(self.bottom_right.x, pos.x) };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RenderStyle {
+    /// ANSI colours for terminals.
+    Ansi,
+    /// Each row followed by a row of markers: `+` under counted part numbers, `-` under
+    /// uncounted ones, `G` under gears.
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CellClass {
+    Empty,
+    CountedPart,
+    UncountedPart,
+    Gear,
+    Symbol,
+}
+
+impl CellClass {
+    fn ansi(self) -> &'static str {
+        match self {
+            CellClass::Empty => "\x1b[0m",
+            CellClass::CountedPart => "\x1b[32m",
+            CellClass::UncountedPart => "\x1b[31m",
+            CellClass::Gear => "\x1b[1;33m",
+            CellClass::Symbol => "\x1b[2m",
+        }
+    }
+
+    fn marker(self) -> char {
+        match self {
+            CellClass::CountedPart => '+',
+            CellClass::UncountedPart => '-',
+            CellClass::Gear => 'G',
+            CellClass::Empty | CellClass::Symbol => ' ',
+        }
+    }
+}
+
+/// Problems with a schematic, rows and columns counted from 1.
+#[derive(Debug, PartialEq)]
+enum SchematicError {
+    Empty,
+    RaggedRow { row: usize, expected: usize, found: usize },
+    NumberOverflow { row: usize, column: usize },
+    /// The value of the symbol at `row`, `column` or the sum of values is too large.
+    ValueOverflow { row: usize, column: usize },
+    Io(String),
+}
+
+impl std::fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchematicError::Empty => write!(f, "empty schematic"),
+            SchematicError::RaggedRow { row, expected, found } => write!(f, "row {} has length {}, expected {}", row, found, expected),
+            SchematicError::NumberOverflow { row, column } => write!(f, "number at row {}, column {} is too large", row, column),
+            SchematicError::ValueOverflow { row, column } => write!(f, "value of symbol at row {}, column {} is too large", row, column),
+            SchematicError::Io(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
 impl std::str::FromStr for Schematic {
-    type Err = ();
+    type Err = SchematicError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let lines: Vec<Vec<u8>> = s.lines().map(|line| line.as_bytes().to_vec()).collect();
-        let width = lines[0].len();
-        for line in &lines {
+        let Some(first) = lines.first() else {
+            return Err(SchematicError::Empty);
+        };
+        let width = first.len();
+        for (y, line) in lines.iter().enumerate() {
             if line.len() != width {
-                return Err(());
+                return Err(SchematicError::RaggedRow { row: y + 1, expected: width, found: line.len() });
             }
         }
-        Ok(Schematic { width, lines })
+        let mut schematic = Schematic {
+            width,
+            lines,
+            parts: vec![],
+            symbols: vec![],
+            part_symbols: HashMap::new(),
+            symbol_parts: HashMap::new(),
+            symbol_at: HashMap::new(),
+        };
+        schematic.build_graph()?;
+        Ok(schematic)
     }
 }
 
 impl Schematic {
-    fn to_u32(s: &[u8]) -> u32 {
-        if let Ok(s) = from_utf8(s) {
-            println!("s = {}", s);
-            return s.parse::<u32>().unwrap();
-        }
-        panic!("invalid input")
+    /// The number made of the ASCII digits `s`, found at `(y, x)`.
+    fn to_part_number(s: &[u8], y: usize, x: usize) -> Result<PartNumber, SchematicError> {
+        s.iter()
+            .try_fold(0 as PartNumber, |acc, digit| acc.checked_mul(10)?.checked_add((digit - b'0') as PartNumber))
+            .ok_or(SchematicError::NumberOverflow { row: y + 1, column: x + 1 })
     }
 
     fn symbols_around(&self, y: usize, x1: usize, l: usize) -> Vec<Symbol> {
@@ -51,8 +248,7 @@ impl Schematic {
             }
             let line = &self.lines[y];
             let skip = x1.saturating_sub(1);
-            for (x, c) in line.iter().enumerate().skip(skip).take(1 + l + 1) {
-                print!("{}", *c as char);
+            for (x, c) in line.iter().enumerate().skip(skip).take(x1 - skip + l + 1) {
                 match c {
                     b'.' => continue,
                     b'0'..=b'9' => continue,
@@ -61,108 +257,332 @@ impl Schematic {
                     }
                 }
             }
-            println!()
         }
-        println!("symbols_around({},{},{}) = {:?}", y, x1, l, symbols);
         symbols
     }
 
-    fn sum_of_part_numbers(&self) -> u32 {
-        let mut sum = 0;
+    /// Finds all parts and symbols, and which of them are next to each other.
+    fn build_graph(&mut self) -> Result<(), SchematicError> {
         for y in 0..self.lines.len() {
-            println!("y = {}", y);
             let line = &self.lines[y];
             let mut x = 0;
             while x < self.width {
                 match line[x] {
                     b'.' => { x += 1; continue },
-                    b'0'..=b'9' => {
-                        // Part number
-                        println!("found part number at ({}, {})", y, x);
+                    b'0'..=b'9' => {},
+                    symbol => {
+                        self.symbol_at.insert(Pos { y, x }, self.symbols.len());
+                        self.symbols.push(Symbol { pos: Pos { y, x }, symbol });
+                        x += 1;
+                        continue
                     },
-                    _ => { x += 1; continue },
                 }
 
                 let mut l = 0;
-                while x+l < self.width && line[x+l] >= b'0' && line[x+l] <= b'9' {
+                while x+l < self.width && line[x+l].is_ascii_digit() {
                     l += 1;
                 }
-
-                // It's a part number, check whether it is connected to a symbol
-                // or not.
-                if !self.symbols_around(y, x, l).is_empty() {
-                    sum += Schematic::to_u32(&line[x..x+l]);
-                }
+                self.parts.push(Part { pos: Pos { y, x }, len: l, number: Schematic::to_part_number(&line[x..x+l], y, x)? });
                 x += l;
             }
         }
+
+        for (part_index, part) in self.parts.iter().enumerate() {
+            for symbol in self.symbols_around(part.pos.y, part.pos.x, part.len) {
+                let symbol_index = self.symbol_at[&symbol.pos];
+                self.part_symbols.entry(part_index).or_default().push(symbol_index);
+                self.symbol_parts.entry(symbol_index).or_default().push(part_index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parts next to the symbol at `(y, x)`, empty if there is no symbol.
+    fn parts_adjacent_to_symbol(&self, y: usize, x: usize) -> Vec<&Part> {
+        self.symbol_at.get(&Pos { y, x })
+            .and_then(|symbol_index| self.symbol_parts.get(symbol_index))
+            .map_or(vec![], |parts| parts.iter().map(|part_index| &self.parts[*part_index]).collect())
+    }
+
+    /// Symbols next to the part with index `part_index`.
+    fn symbols_adjacent_to_part(&self, part_index: usize) -> Vec<&Symbol> {
+        self.part_symbols.get(&part_index)
+            .map_or(vec![], |symbols| symbols.iter().map(|symbol_index| &self.symbols[*symbol_index]).collect())
+    }
+
+    fn sum_of_part_numbers(&self) -> Total {
+        let mut sum = 0;
+        for (part_index, part) in self.parts.iter().enumerate() {
+            if !self.symbols_adjacent_to_part(part_index).is_empty() {
+                sum += part.number as Total;
+            }
+        }
         sum
     }
 
-    fn sum_of_gear_ratios(&self) -> u32 {
-        // Gears that might be connected to parts
-        let mut gears: Vec<(Pos, Vec<PartNumber>)> = vec![];
+    fn sum_of_gear_ratios(&self) -> Total {
+        self.evaluate(&GearRule::gear()).expect("gear ratios fit into a Total")
+    }
 
-        for y in 0..self.lines.len() {
-            println!("y = {}", y);
-            let line = &self.lines[y];
-            let mut x = 0;
-            while x < self.width {
-                match line[x] {
-                    b'.' => { x += 1; continue },
-                    b'0'..=b'9' => {
-                        // Part number
-                        println!("found part number at ({}, {})", y, x);
-                    },
-                    _ => { x += 1; continue },
-                }
+    /// Sum of the values of all symbols that are gears according to `rule`.
+    fn evaluate(&self, rule: &GearRule) -> Result<Total, SchematicError> {
+        let mut sum: Total = 0;
+        for symbol in self.symbols.iter().filter(|symbol| rule.applies_to(symbol.symbol)) {
+            let parts = self.parts_adjacent_to_symbol(symbol.pos.y, symbol.pos.x);
+            if rule.parts.matches(parts.len()) {
+                sum = rule.aggregation.apply(parts.iter().map(|part| part.number))
+                    .and_then(|value| sum.checked_add(value))
+                    .ok_or(SchematicError::ValueOverflow { row: symbol.pos.y + 1, column: symbol.pos.x + 1 })?;
+            }
+        }
+        Ok(sum)
+    }
 
-                let mut l = 0;
-                while x+l < self.width && line[x+l] >= b'0' && line[x+l] <= b'9' {
-                    l += 1;
-                }
+    fn cell_classes(&self) -> Vec<Vec<CellClass>> {
+        let mut classes = vec![vec![CellClass::Empty; self.width]; self.lines.len()];
+        for (part_index, part) in self.parts.iter().enumerate() {
+            let class = if self.symbols_adjacent_to_part(part_index).is_empty() { CellClass::UncountedPart } else { CellClass::CountedPart };
+            classes[part.pos.y][part.pos.x..part.pos.x+part.len].fill(class);
+        }
+        let gear = GearRule::gear();
+        for symbol in self.symbols.iter() {
+            let is_gear = gear.applies_to(symbol.symbol) && gear.parts.matches(self.parts_adjacent_to_symbol(symbol.pos.y, symbol.pos.x).len());
+            classes[symbol.pos.y][symbol.pos.x] = if is_gear { CellClass::Gear } else { CellClass::Symbol };
+        }
+        classes
+    }
 
-                // It's a part number, check whether it is connected to a symbol
-                // or not.
-                for symbol in self.symbols_around(y, x, l) {
-                    if symbol.symbol != b'*' {
-                        continue
+    /// The schematic with counted and uncounted part numbers, gears and other symbols
+    /// told apart.
+    fn render(&self, style: RenderStyle) -> String {
+        let mut result = String::new();
+        for (line, classes) in self.lines.iter().zip(self.cell_classes()) {
+            match style {
+                RenderStyle::Ansi => {
+                    let mut current = CellClass::Empty;
+                    for (c, class) in line.iter().zip(classes.iter()) {
+                        if *class != current {
+                            if current != CellClass::Empty {
+                                result.push_str(CellClass::Empty.ansi());
+                            }
+                            if *class != CellClass::Empty {
+                                result.push_str(class.ansi());
+                            }
+                            current = *class;
+                        }
+                        result.push(*c as char);
+                    }
+                    if current != CellClass::Empty {
+                        result.push_str(CellClass::Empty.ansi());
                     }
-                    let part_number = Schematic::to_u32(&line[x..x+l]);
-                    let open_gear = gears.iter_mut().find(|(gear, _)| gear.x == symbol.pos.x && gear.y == symbol.pos.y);
-                    if let Some((_, parts)) = open_gear {
-                        parts.push(part_number);
-                    } else {
-                        gears.push((symbol.pos, vec![part_number]));
+                    result.push('\n');
+                },
+                RenderStyle::Plain => {
+                    result.extend(line.iter().map(|c| *c as char));
+                    result.push('\n');
+                    let markers: String = classes.iter().map(|class| class.marker()).collect();
+                    if !markers.trim_end().is_empty() {
+                        result.push_str(markers.trim_end());
+                        result.push('\n');
+                    }
+                },
+            }
+        }
+        result
+    }
+
+    /// Groups parts that are connected through shared symbols, and with `numbers_touch`
+    /// also parts whose digits touch. Every part is in exactly one cluster, symbols
+    /// without parts are in none.
+    fn clusters(&self, numbers_touch: bool) -> Vec<Cluster> {
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        fn union(parent: &mut [usize], a: usize, b: usize) {
+            let (a, b) = (find(parent, a), find(parent, b));
+            parent[std::cmp::max(a, b)] = std::cmp::min(a, b);
+        }
+        let mut parent: Vec<usize> = (0..self.parts.len()).collect();
+
+        for parts in self.symbol_parts.values() {
+            for part_index in parts.iter().skip(1) {
+                union(&mut parent, parts[0], *part_index);
+            }
+        }
+        if numbers_touch {
+            let mut part_at = HashMap::new();
+            for (part_index, part) in self.parts.iter().enumerate() {
+                for x in part.pos.x..part.pos.x+part.len {
+                    part_at.insert(Pos { y: part.pos.y, x }, part_index);
+                }
+            }
+            // Looking at the row below is enough, the row above was seen from the other part.
+            for (part_index, part) in self.parts.iter().enumerate() {
+                for x in part.pos.x.saturating_sub(1)..=part.pos.x+part.len {
+                    if let Some(other) = part_at.get(&Pos { y: part.pos.y + 1, x }) {
+                        union(&mut parent, part_index, *other);
                     }
                 }
-                x += l;
             }
         }
 
-        println!("gears = {:?}", gears);
+        let mut clusters: Vec<Cluster> = vec![];
+        let mut cluster_of_root = HashMap::new();
+        for (part_index, part) in self.parts.iter().enumerate() {
+            let root = find(&mut parent, part_index);
+            let cluster_index = *cluster_of_root.entry(root).or_insert_with(|| {
+                clusters.push(Cluster { parts: vec![], symbols: vec![], top_left: part.pos, bottom_right: part.pos });
+                clusters.len() - 1
+            });
+            let cluster = &mut clusters[cluster_index];
+            cluster.parts.push(part_index);
+            cluster.extend(part.pos);
+            cluster.extend(Pos { y: part.pos.y, x: part.pos.x + part.len - 1 });
+            for symbol_index in self.part_symbols.get(&part_index).into_iter().flatten() {
+                if !cluster.symbols.contains(symbol_index) {
+                    cluster.symbols.push(*symbol_index);
+                    cluster.extend(self.symbols[*symbol_index].pos);
+                }
+            }
+        }
+        clusters
+    }
+}
 
-        // Calculate the sum of the gear ratios where each gear is connected to
-        // exactly two parts.
-        let mut sum = 0;
-        for (_, parts) in gears {
-            if parts.len() == 2 {
-                let ratio = parts[0] * parts[1];
-                sum += ratio;
+fn is_symbol(c: u8) -> bool {
+    c != b'.' && !c.is_ascii_digit()
+}
+
+/// A row of the streaming window, with its numbers as `(x, len, number)`.
+struct Row {
+    bytes: Vec<u8>,
+    numbers: Vec<(usize, usize, PartNumber)>,
+}
+
+impl Row {
+    fn new(bytes: Vec<u8>, y: usize) -> Result<Row, SchematicError> {
+        let mut numbers = vec![];
+        let mut x = 0;
+        while x < bytes.len() {
+            if !bytes[x].is_ascii_digit() {
+                x += 1;
+                continue;
+            }
+            let mut l = 0;
+            while x+l < bytes.len() && bytes[x+l].is_ascii_digit() {
+                l += 1;
             }
+            numbers.push((x, l, Schematic::to_part_number(&bytes[x..x+l], y, x)?));
+            x += l;
+        }
+        Ok(Row { bytes, numbers })
+    }
+}
+
+/// Adds the part numbers and gear ratios of `current` to `totals`, looking only at
+/// the rows directly above and below.
+fn process_row(above: Option<&Row>, current: &Row, below: Option<&Row>, totals: &mut (Total, Total)) {
+    let window: Vec<&Row> = [above, Some(current), below].into_iter().flatten().collect();
+
+    for (x, l, number) in current.numbers.iter() {
+        let from = x.saturating_sub(1);
+        let to = std::cmp::min(x + l + 1, current.bytes.len());
+        if window.iter().any(|row| row.bytes[from..to].iter().any(|c| is_symbol(*c))) {
+            totals.0 += *number as Total;
         }
-        sum
     }
 
+    for (x, c) in current.bytes.iter().enumerate() {
+        if *c != b'*' {
+            continue;
+        }
+        let parts: Vec<PartNumber> = window.iter()
+            .flat_map(|row| row.numbers.iter())
+            .filter(|(start, l, _)| *start <= x + 1 && x <= start + l)
+            .map(|(_, _, number)| *number)
+            .collect();
+        if parts.len() == 2 {
+            totals.1 += parts[0] as Total * parts[1] as Total;
+        }
+    }
+}
+
+/// Sum of part numbers and sum of gear ratios, reading the schematic one row at a
+/// time and only keeping three rows in memory.
+fn stream_totals<R: BufRead>(reader: R) -> Result<(Total, Total), SchematicError> {
+    let mut totals = (0, 0);
+    let mut width = None;
+    let mut above: Option<Row> = None;
+    let mut current: Option<Row> = None;
+    for (y, line) in reader.lines().enumerate() {
+        let line = line.map_err(|error| SchematicError::Io(error.to_string()))?;
+        let expected = *width.get_or_insert(line.len());
+        if line.len() != expected {
+            return Err(SchematicError::RaggedRow { row: y + 1, expected, found: line.len() });
+        }
+        let below = Row::new(line.into_bytes(), y)?;
+        if let Some(current) = &current {
+            process_row(above.as_ref(), current, Some(&below), &mut totals);
+        }
+        above = current;
+        current = Some(below);
+    }
+    let Some(current) = &current else {
+        return Err(SchematicError::Empty);
+    };
+    process_row(above.as_ref(), current, None, &mut totals);
+    Ok(totals)
 }
 
 pub fn main() {
+    if std::env::args().any(|arg| arg == "--stream") {
+        let totals = std::fs::File::open("day3.input")
+            .map_err(|error| SchematicError::Io(error.to_string()))
+            .and_then(|file| stream_totals(std::io::BufReader::new(file)));
+        match totals {
+            Ok((part_numbers, gear_ratios)) => {
+                println!("sum_of_part_numbers = {}", part_numbers);
+                println!("sum_of_gear_ratios = {}", gear_ratios);
+            },
+            Err(reason) => println!("error = {}", reason)
+        }
+        return
+    }
+
     match std::fs::read_to_string("day3.input") {
-        Ok(input) => {
-            if let Ok(schematic) = input.parse::<Schematic>() {
+        Ok(input) => match input.parse::<Schematic>() {
+            Ok(schematic) => {
                 println!("sum_of_part_numbers = {}", schematic.sum_of_part_numbers());
                 println!("sum_of_gear_ratios = {}", schematic.sum_of_gear_ratios());
-            }
+                for arg in std::env::args().skip(1) {
+                    if arg == "--render" {
+                        print!("{}", schematic.render(RenderStyle::Ansi));
+                    } else if arg == "--render-plain" {
+                        print!("{}", schematic.render(RenderStyle::Plain));
+                    }
+                    if arg == "--clusters" || arg == "--clusters-touching" {
+                        for cluster in schematic.clusters(arg == "--clusters-touching") {
+                            let numbers: Vec<PartNumber> = cluster.parts.iter().map(|part_index| schematic.parts[*part_index].number).collect();
+                            let symbols: String = cluster.symbols.iter().map(|symbol_index| schematic.symbols[*symbol_index].symbol as char).collect();
+                            println!("cluster {:?}..{:?}: parts = {:?}, symbols = {}", cluster.top_left, cluster.bottom_right, numbers, symbols);
+                        }
+                    }
+                    if let Some(rule) = arg.strip_prefix("--rule=") {
+                        match rule.parse::<GearRule>() {
+                            Ok(rule) => match schematic.evaluate(&rule) {
+                                Ok(value) => println!("{:?} = {}", rule, value),
+                                Err(reason) => println!("error = {}", reason),
+                            },
+                            Err(reason) => println!("error = {}", reason),
+                        }
+                    }
+                }
+            },
+            Err(reason) => println!("error = {}", reason)
         },
         Err(reason) => println!("error = {}", reason)
     }
@@ -172,30 +592,44 @@ pub fn main() {
 mod tests {
     use super::*;
 
+    static DATA: &str = "467..114..
+...*......
+..35..633.
+......#...
+617*......
+.....+.58.
+..592.....
+......755.
+...$.*....
+.664.598..";
+
+    /// Parts around `*`, `#` and `$` in different numbers.
+    static RULES: &str = "2.3.....
+.#...*..
+1.4.6.5.
+........
+7$8.....";
+
     #[test]
     fn symbols_around_trivial_false() {
-        let schematic = Schematic { width: 3, lines: vec!["123".as_bytes().to_vec()] };
+        let schematic = "123".parse::<Schematic>().unwrap();
         assert_eq!(schematic.symbols_around(0, 0, 3), vec![]);
     }
 
     #[test]
     fn symbols_around_just_before() {
-        let schematic = Schematic { width: 4, lines: vec!["*123".as_bytes().to_vec()] };
+        let schematic = "*123".parse::<Schematic>().unwrap();
         assert_eq!(schematic.symbols_around(0, 1, 3), vec![ Symbol { pos: Pos { y: 0 , x: 0 }, symbol: b'*' } ]);
     }
 
+    #[test]
+    fn symbols_around_left_edge() {
+        let schematic = "12.*".parse::<Schematic>().unwrap();
+        assert_eq!(schematic.symbols_around(0, 0, 2), vec![]);
+    }
+
     #[test]
     fn sum_of_part_numbers_example() {
-        static DATA: &str = "467..114..
-...*......
-..35..633.
-......#...
-617*......
-.....+.58.
-..592.....
-......755.
-...$.*....
-.664.598..";
         let schematic = DATA.parse::<Schematic>().unwrap();
         assert_eq!(schematic.sum_of_part_numbers(), 4361);
     }
@@ -211,17 +645,203 @@ mod tests {
 
     #[test]
     fn sum_of_gear_ratios_example() {
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.sum_of_gear_ratios(), 467835);
+    }
+
+    #[test]
+    fn adjacency_queries() {
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        let numbers = |parts: Vec<&Part>| parts.iter().map(|part| part.number).collect::<Vec<_>>();
+        assert_eq!(numbers(schematic.parts_adjacent_to_symbol(1, 3)), vec![467, 35]);
+        assert_eq!(numbers(schematic.parts_adjacent_to_symbol(8, 5)), vec![755, 598]);
+        assert_eq!(numbers(schematic.parts_adjacent_to_symbol(0, 0)), Vec::<u32>::new());
+
+        let index_114 = schematic.parts.iter().position(|part| part.number == 114).unwrap();
+        assert!(schematic.symbols_adjacent_to_part(index_114).is_empty());
+        let index_617 = schematic.parts.iter().position(|part| part.number == 617).unwrap();
+        assert_eq!(schematic.symbols_adjacent_to_part(index_617), vec![&Symbol { pos: Pos { y: 4, x: 3 }, symbol: b'*' }]);
+    }
+
+    #[test]
+    fn evaluate_rule_variants() {
+        let schematic = RULES.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.sum_of_gear_ratios(), 6 * 5);
+
+        // Any symbol touching at least three parts, sum of the parts.
+        let rule = GearRule { symbols: None, parts: PartCount::AtLeast(3), aggregation: Aggregation::Sum };
+        assert_eq!(schematic.evaluate(&rule), Ok(2 + 3 + 1 + 4));
+
+        let rule = GearRule { symbols: Some(vec![b'#', b'$']), parts: PartCount::Between(2, 4), aggregation: Aggregation::Max };
+        assert_eq!(schematic.evaluate(&rule), Ok(4 + 8));
+
+        let rule = GearRule { symbols: Some(vec![b'$']), parts: PartCount::Exactly(3), aggregation: Aggregation::Product };
+        assert_eq!(schematic.evaluate(&rule), Ok(0));
+    }
+
+    #[test]
+    fn parse_gear_rule() {
+        let schematic = RULES.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.evaluate(&"*:2:product".parse().unwrap()), Ok(30));
+        assert_eq!(schematic.evaluate(&"any:3+:sum".parse().unwrap()), Ok(10));
+        assert_eq!(schematic.evaluate(&"#$:2-4:max".parse().unwrap()), Ok(12));
+        assert!("*:2".parse::<GearRule>().is_err());
+        assert!("*:x:sum".parse::<GearRule>().is_err());
+        assert!("*:2:mean".parse::<GearRule>().is_err());
+    }
+
+    #[test]
+    fn clusters_shared_symbols() {
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        let numbers = |cluster: &Cluster| cluster.parts.iter().map(|part_index| schematic.parts[*part_index].number).collect::<Vec<_>>();
+
+        let clusters = schematic.clusters(false);
+        assert_eq!(clusters.iter().map(numbers).collect::<Vec<_>>(), vec![
+            vec![467, 35], vec![114], vec![633], vec![617], vec![58], vec![592], vec![755, 598], vec![664],
+        ]);
+        assert_eq!(clusters[0].symbols, vec![0]);
+        assert_eq!((clusters[0].top_left, clusters[0].bottom_right), (Pos { y: 0, x: 0 }, Pos { y: 2, x: 3 }));
+        assert_eq!((clusters[6].top_left, clusters[6].bottom_right), (Pos { y: 7, x: 5 }, Pos { y: 9, x: 8 }));
+        assert!(clusters[1].symbols.is_empty());
+    }
+
+    #[test]
+    fn clusters_numbers_touch() {
+        static DATA: &str = "12....
+..34*5
+......
+7.....
+.8.9..";
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        let numbers = |cluster: &Cluster| cluster.parts.iter().map(|part_index| schematic.parts[*part_index].number).collect::<Vec<_>>();
+
+        let clusters = schematic.clusters(false);
+        assert_eq!(clusters.iter().map(numbers).collect::<Vec<_>>(), vec![vec![12], vec![34, 5], vec![7], vec![8], vec![9]]);
+
+        let clusters = schematic.clusters(true);
+        assert_eq!(clusters.iter().map(numbers).collect::<Vec<_>>(), vec![vec![12, 34, 5], vec![7, 8], vec![9]]);
+        assert_eq!((clusters[0].top_left, clusters[0].bottom_right), (Pos { y: 0, x: 0 }, Pos { y: 1, x: 5 }));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("".parse::<Schematic>().err(), Some(SchematicError::Empty));
+        assert_eq!("123\n.*.\n..".parse::<Schematic>().err(), Some(SchematicError::RaggedRow { row: 3, expected: 3, found: 2 }));
+        let error = "..*.........\n.99999999999".parse::<Schematic>().err().unwrap();
+        assert_eq!(error, SchematicError::NumberOverflow { row: 2, column: 2 });
+        assert_eq!(error.to_string(), "number at row 2, column 2 is too large");
+        assert!("4294967295*".parse::<Schematic>().is_ok());
+    }
+
+    #[test]
+    fn sums_beyond_u32() {
+        static DATA: &str = "4000000000.4000000000
+..........*..........
+..........3000000000.";
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.sum_of_part_numbers(), 11_000_000_000);
+        assert_eq!(schematic.sum_of_gear_ratios(), 0);
+        assert_eq!(schematic.evaluate(&"*:3:sum".parse().unwrap()), Ok(11_000_000_000));
+        assert_eq!(stream_totals(DATA.as_bytes()).unwrap().0, 11_000_000_000);
+
+        let schematic = "4000000000*3000000000".parse::<Schematic>().unwrap();
+        assert_eq!(schematic.sum_of_gear_ratios(), 12_000_000_000_000_000_000);
+        assert_eq!(stream_totals("4000000000*3000000000".as_bytes()).unwrap(), (7_000_000_000, 12_000_000_000_000_000_000));
+    }
+
+    #[test]
+    fn sums_beyond_u64() {
+        static DATA: &str = "4000000000*4000000000
+.....................
+4000000000*4000000000";
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.sum_of_gear_ratios(), 32_000_000_000_000_000_000);
+        assert_eq!(stream_totals(DATA.as_bytes()).unwrap(), (16_000_000_000, 32_000_000_000_000_000_000));
+
+        let schematic = "4000000000.4000000000\n..........*..........\n..........4000000000.".parse::<Schematic>().unwrap();
+        assert_eq!(schematic.evaluate(&"*:3:product".parse().unwrap()), Ok(64_000_000_000_000_000_000_000_000_000));
+    }
+
+    #[test]
+    fn value_overflow() {
+        // Both gears touch all four parts, 2.56e38 each: fine on their own, too large together.
+        static DATA: &str = "4000000000*4000000000
+4000000000*4000000000";
+        let schematic = DATA.parse::<Schematic>().unwrap();
+        let error = schematic.evaluate(&"*:4:product".parse().unwrap()).err().unwrap();
+        assert_eq!(error, SchematicError::ValueOverflow { row: 2, column: 11 });
+        assert_eq!(error.to_string(), "value of symbol at row 2, column 11 is too large");
+        assert_eq!(schematic.evaluate(&"*:4:sum".parse().unwrap()), Ok(32_000_000_000));
+
+        static SIX: &str = "4000000000.4000000000
+4000000000*4000000000
+4000000000.4000000000";
+        let schematic = SIX.parse::<Schematic>().unwrap();
+        assert_eq!(schematic.evaluate(&"*:3+:product".parse().unwrap()), Err(SchematicError::ValueOverflow { row: 2, column: 11 }));
+    }
+
+    #[test]
+    fn render_plain() {
         static DATA: &str = "467..114..
 ...*......
 ..35..633.
 ......#...
-617*......
-.....+.58.
-..592.....
-......755.
-...$.*....
-.664.598..";
+617*......";
         let schematic = DATA.parse::<Schematic>().unwrap();
-        assert_eq!(schematic.sum_of_gear_ratios(), 467835);
+        assert_eq!(schematic.render(RenderStyle::Plain), "467..114..
++++  ---
+...*......
+   G
+..35..633.
+  ++  +++
+......#...
+617*......
++++
+");
+    }
+
+    #[test]
+    fn render_ansi() {
+        let schematic = "12.5\n..*#".parse::<Schematic>().unwrap();
+        assert_eq!(schematic.render(RenderStyle::Ansi), "\x1b[32m12\x1b[0m.\x1b[32m5\x1b[0m\n..\x1b[1;33m*\x1b[0m\x1b[2m#\x1b[0m\n");
+        let schematic = "12..\n...#".parse::<Schematic>().unwrap();
+        assert_eq!(schematic.render(RenderStyle::Ansi), "\x1b[31m12\x1b[0m..\n...\x1b[2m#\x1b[0m\n");
+    }
+
+    #[test]
+    fn stream_totals_example() {
+        assert_eq!(stream_totals(DATA.as_bytes()).unwrap(), (4361, 467835));
+        assert_eq!(stream_totals("12.\n.*".as_bytes()), Err(SchematicError::RaggedRow { row: 2, expected: 3, found: 2 }));
+        assert_eq!(stream_totals("".as_bytes()), Err(SchematicError::Empty));
+        assert_eq!(stream_totals("..\n.*\n99999999999.".as_bytes()), Err(SchematicError::RaggedRow { row: 3, expected: 2, found: 12 }));
+        assert_eq!(stream_totals("...........\n99999999999".as_bytes()), Err(SchematicError::NumberOverflow { row: 2, column: 1 }));
+    }
+
+    fn generate_schematic(width: usize, height: usize, seed: u32) -> String {
+        let mut state = seed;
+        let mut result = String::with_capacity((width + 1) * height);
+        for _ in 0..height {
+            for _ in 0..width {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                result.push(match (state >> 16) % 20 {
+                    0 => '*',
+                    1 => '#',
+                    2..=7 => (b'0' + ((state >> 8) % 10) as u8) as char,
+                    _ => '.',
+                });
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    #[test]
+    fn stream_totals_matches_schematic() {
+        for seed in 1..=3 {
+            let data = generate_schematic(150, 120, seed);
+            let schematic = data.parse::<Schematic>().unwrap();
+            let expected = (schematic.sum_of_part_numbers(), schematic.sum_of_gear_ratios());
+            assert_eq!(stream_totals(data.as_bytes()).unwrap(), expected, "seed = {}", seed);
+        }
     }
 }
@@ -1,21 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 struct Scratchcard {
     id: u32,
-    numbers: Vec<u32>,
-    winning_numbers: Vec<u32>,
+    numbers: HashSet<u32>,
+    winning_numbers: HashSet<u32>,
+    /// Numbers listed more than once on the same side of the card, in input order.
+    duplicates: Vec<u32>,
 }
 
 impl std::str::FromStr for Scratchcard {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        println!("s = {}", s);
-        let mut result = Scratchcard { id: 0, numbers: vec![], winning_numbers: vec![] };
+        let mut result = Scratchcard { id: 0, numbers: HashSet::new(), winning_numbers: HashSet::new(), duplicates: vec![] };
         let mut state = 0;
         for tok in s.split(&[' ', ':'][..]) {
-            println!("state = {}, tok = {}", state, tok);
             if tok.trim().is_empty() {
                 continue;
             }
@@ -32,7 +32,9 @@ impl std::str::FromStr for Scratchcard {
                 }
             } else if state == 1 {
                 if let Ok(number) = tok.parse::<u32>() {
-                    result.numbers.push(number);
+                    if !result.numbers.insert(number) {
+                        result.duplicates.push(number);
+                    }
                 } else if tok == "|" {
                     state = 2;
                 } else {
@@ -40,14 +42,15 @@ impl std::str::FromStr for Scratchcard {
                 }
             } else if state == 2 {
                 if let Ok(number) = tok.parse::<u32>() {
-                    result.winning_numbers.push(number);
+                    if !result.winning_numbers.insert(number) {
+                        result.duplicates.push(number);
+                    }
                 } else {
                     return Err("invalid input")
                 }
             }
         }
         if result.id != 0 && !result.numbers.is_empty() && !result.winning_numbers.is_empty() {
-            println!("result = {:?}", result);
             return Ok(result)
         }
         Err("invalid input")
@@ -56,27 +59,106 @@ impl std::str::FromStr for Scratchcard {
 
 impl Scratchcard {
     fn count_winning(&self) -> u32 {
-        let mut winning = 0;
-        for number in &self.numbers {
-            if self.winning_numbers.contains(number) {
-                winning += 1;
-            }
-        }
-        winning
+        self.numbers.intersection(&self.winning_numbers).count() as u32
     }
 
-    fn count_points(&self) -> u32 {
-        let winning = self.count_winning();
+    fn count_points<R: ScoringRule + ?Sized>(&self, rule: &R) -> u64 {
+        rule.points(self.count_winning())
+    }
+}
+
+/// Points for a card with `winning` winning numbers.
+trait ScoringRule {
+    fn points(&self, winning: u32) -> u64;
+}
+
+/// 1 point for the first winning number, doubled for every further one.
+struct Doubling;
+
+/// 1 point per winning number.
+struct Linear;
+
+/// 1, 1, 2, 3, 5, ... points.
+struct Fibonacci;
+
+/// Points looked up by number of winning numbers, the last entry repeating for more.
+struct PointsTable(Vec<u64>);
+
+impl ScoringRule for Doubling {
+    fn points(&self, winning: u32) -> u64 {
         if winning == 0 {
             return 0;
         }
-        2_u32.pow(winning - 1)
+        1_u64.checked_shl(winning - 1).unwrap_or(u64::MAX)
+    }
+}
+
+impl ScoringRule for Linear {
+    fn points(&self, winning: u32) -> u64 {
+        winning as u64
+    }
+}
+
+impl ScoringRule for Fibonacci {
+    fn points(&self, winning: u32) -> u64 {
+        let (mut a, mut b) = (0_u64, 1_u64);
+        for _ in 0..winning {
+            (a, b) = (b, a.saturating_add(b));
+        }
+        a
+    }
+}
+
+impl ScoringRule for PointsTable {
+    fn points(&self, winning: u32) -> u64 {
+        self.0.get(winning as usize).or(self.0.last()).copied().unwrap_or(0)
+    }
+}
+
+/// `doubling`, `linear`, `fibonacci` or `table:P0,P1,P2,...`.
+fn scoring_rule(name: &str) -> Option<Box<dyn ScoringRule>> {
+    match name {
+        "doubling" => Some(Box::new(Doubling)),
+        "linear" => Some(Box::new(Linear)),
+        "fibonacci" => Some(Box::new(Fibonacci)),
+        _ => {
+            let table = name.strip_prefix("table:")?;
+            let points: Result<Vec<u64>, _> = table.split(',').map(|p| p.trim().parse::<u64>()).collect();
+            Some(Box::new(PointsTable(points.ok()?)))
+        }
+    }
+}
+
+/// Copies won by each instance of a card, for each of the next `winning` cards.
+trait CascadeRule {
+    /// Copies of the card `offset` places further, counting from 1.
+    fn copies(&self, offset: u32, winning: u32) -> u128;
+}
+
+/// `factor` copies of each of the next cards; the puzzle's rule is `Factor(1)`.
+struct Factor(u128);
+
+/// `winning` copies of the next card, one fewer of the card after that, and so on
+/// down to one copy of the last card won.
+struct Tapering;
+
+impl CascadeRule for Factor {
+    fn copies(&self, _offset: u32, _winning: u32) -> u128 {
+        self.0
+    }
+}
+
+impl CascadeRule for Tapering {
+    fn copies(&self, offset: u32, winning: u32) -> u128 {
+        (winning + 1 - offset) as u128
     }
 }
 
 #[derive(Debug)]
 struct PileOfScratchcards {
     cards: Vec<Scratchcard>,
+    /// Lines (counted from 1) that could not be parsed, with the reason.
+    dropped: Vec<(usize, &'static str)>,
 }
 
 impl std::str::FromStr for PileOfScratchcards {
@@ -84,54 +166,228 @@ impl std::str::FromStr for PileOfScratchcards {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut cards = vec![];
-        for line in s.lines() {
-            if let Ok(card) = line.parse::<Scratchcard>() {
-                cards.push(card);
+        let mut dropped = vec![];
+        for (index, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse::<Scratchcard>() {
+                Ok(card) => cards.push(card),
+                Err(reason) => dropped.push((index + 1, reason)),
             }
         }
-        Ok(PileOfScratchcards { cards })
+        Ok(PileOfScratchcards { cards, dropped })
     }
 }
 
+/// Problems with the card ids of a pile, which the cascade expects to be 1, 2, 3, ...
+#[derive(Debug, PartialEq)]
+enum OrderProblem {
+    FirstNotOne(u32),
+    Duplicate(u32),
+    OutOfOrder { previous: u32, id: u32 },
+    Gap { previous: u32, id: u32 },
+}
+
+impl std::fmt::Display for OrderProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderProblem::FirstNotOne(id) => write!(f, "first card is {}, not 1", id),
+            OrderProblem::Duplicate(id) => write!(f, "card {} appears more than once", id),
+            OrderProblem::OutOfOrder { previous, id } => write!(f, "card {} comes after card {}", id, previous),
+            OrderProblem::Gap { previous, id } => write!(f, "cards {} to {} are missing", previous + 1, id - 1),
+        }
+    }
+}
+
+/// What happens to copies of cards past the last card of the pile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PastEnd {
+    /// Drop them, there are no such cards.
+    Ignore,
+    /// Refuse to play the pile.
+    Error,
+    /// Give them to the last card instead.
+    Clamp,
+}
+
 impl PileOfScratchcards {
-    fn count_points(&self) -> u32 {
-        let mut points = 0;
+    fn count_points<R: ScoringRule + ?Sized>(&self, rule: &R) -> u64 {
+        let mut points: u64 = 0;
         for card in &self.cards {
-            points += card.count_points();
+            points = points.saturating_add(card.count_points(rule));
         }
         points
     }
 
-    fn count_cards_part_2(&self) -> usize {
-        let mut dups = HashMap::<u32, usize>::new();
-        let mut cards = 0;
-        for card in &self.cards {
-            let mut cards_of_id = 1;
-            if let Some(dups_of_id) = dups.get(&card.id) {
-                cards_of_id += dups_of_id;
+    /// Cards that list a number more than once, with those numbers.
+    fn duplicated_numbers(&self) -> Vec<(u32, &[u32])> {
+        self.cards.iter()
+            .filter(|card| !card.duplicates.is_empty())
+            .map(|card| (card.id, &card.duplicates[..]))
+            .collect()
+    }
+
+    fn check_order(&self) -> Vec<OrderProblem> {
+        let mut problems = vec![];
+        if let Some(first) = self.cards.first() {
+            if first.id != 1 {
+                problems.push(OrderProblem::FirstNotOne(first.id));
             }
-            println!("cards of id {} = {}", card.id, cards_of_id);
-            cards += cards_of_id;
+        }
+        let mut seen = HashSet::new();
+        let mut previous: Option<u32> = None;
+        for card in self.cards.iter() {
+            let id = card.id;
+            if !seen.insert(id) {
+                problems.push(OrderProblem::Duplicate(id));
+                continue;
+            }
+            match previous {
+                Some(previous) if id < previous => problems.push(OrderProblem::OutOfOrder { previous, id }),
+                Some(previous) if id > previous + 1 => problems.push(OrderProblem::Gap { previous, id }),
+                _ => {},
+            }
+            previous = Some(id);
+        }
+        problems
+    }
+
+    /// Sorts the cards by id and numbers them 1, 2, 3, ... again, so that the cascade
+    /// works on piles with gaps or in the wrong order.
+    fn reindex(&mut self) {
+        self.cards.sort_by_key(|card| card.id);
+        for (index, card) in self.cards.iter_mut().enumerate() {
+            card.id = index as u32 + 1;
+        }
+    }
+
+    fn count_cards_part_2<R: CascadeRule + ?Sized>(&self, past_end: PastEnd, rule: &R) -> Result<u128, &'static str> {
+        let trace = self.trace_cascade_with(past_end, rule)?;
+        trace.copies.iter().try_fold(0_u128, |sum, copies| sum.checked_add(*copies)).ok_or("too many cards")
+    }
+
+    /// Plays the pile, recording how many copies of every card there are and where
+    /// they came from.
+    fn trace_cascade_with<R: CascadeRule + ?Sized>(&self, past_end: PastEnd, rule: &R) -> Result<CascadeTrace, &'static str> {
+        let last_id = self.cards.iter().map(|card| card.id).max().unwrap_or(0);
+        let mut dups = HashMap::<u32, u128>::new();
+        let mut trace = CascadeTrace { copies: vec![], contributions: vec![], most_descendants: None };
+        // Copies won by a single instance of each card, in the same order as the contributions.
+        let mut per_instance = vec![];
+        for card in &self.cards {
+            let cards_of_id = dups.get(&card.id).copied().unwrap_or(0).checked_add(1).ok_or("too many cards")?;
+            trace.copies.push(cards_of_id);
 
             // Distribute the winning cards further
+            let mut contributions = vec![];
+            let mut won_per_instance = vec![];
             let winning = card.count_winning();
-            println!("winning = {}", winning);
             for i in 1..=winning {
-                dups.entry(card.id + i)
-                    .and_modify(|e| *e += cards_of_id )
-                    .or_insert(cards_of_id);
+                let mut id = card.id + i;
+                if id > last_id {
+                    match past_end {
+                        PastEnd::Ignore => break,
+                        PastEnd::Error => return Err("cascade runs past the last card"),
+                        PastEnd::Clamp if card.id < last_id => id = last_id,
+                        PastEnd::Clamp => break,
+                    }
+                }
+                let dups_of_id = dups.entry(id).or_insert(0);
+                let copies = rule.copies(i, winning);
+                let won = cards_of_id.checked_mul(copies).ok_or("too many cards")?;
+                *dups_of_id = dups_of_id.checked_add(won).ok_or("too many cards")?;
+                contributions.push((id, won));
+                won_per_instance.push(copies);
+            }
+            trace.contributions.push(contributions);
+            per_instance.push(won_per_instance);
+        }
+
+        // Copies produced by one instance of each card, directly or through the copies
+        // it won. Later cards never give copies to earlier ones, so go backwards.
+        let position: HashMap<u32, usize> = self.cards.iter().enumerate().map(|(index, card)| (card.id, index)).collect();
+        let mut descendants = vec![0_u128; self.cards.len()];
+        for index in (0..self.cards.len()).rev() {
+            for ((id, _), copies) in trace.contributions[index].iter().zip(per_instance[index].iter()) {
+                if let Some(target) = position.get(id) {
+                    descendants[index] = descendants[*target]
+                        .checked_add(1)
+                        .and_then(|d| d.checked_mul(*copies))
+                        .and_then(|d| d.checked_add(descendants[index]))
+                        .ok_or("too many cards")?;
+                }
             }
         }
-        cards
+        trace.most_descendants = descendants.iter().enumerate()
+            .max_by_key(|(index, d)| (**d, std::cmp::Reverse(*index)))
+            .map(|(index, d)| (self.cards[index].id, *d));
+        Ok(trace)
     }
 }
 
+/// How the copies of the scratchcards came about, per card in pile order.
+#[derive(Debug)]
+struct CascadeTrace {
+    /// Instances of each card, including the original.
+    copies: Vec<u128>,
+    /// For each card, the card ids it gave copies to and how many copies each got.
+    contributions: Vec<Vec<(u32, u128)>>,
+    /// The card whose original produced the most copies, and how many that were.
+    most_descendants: Option<(u32, u128)>,
+}
+
 pub fn main() {
     match std::fs::read_to_string("day4.input") {
         Ok(input) => {
-            if let Ok(scratchcards) = input.parse::<PileOfScratchcards>() {
-                println!("points = {}", scratchcards.count_points());
-                println!("cards = {}", scratchcards.count_cards_part_2());
+            if let Ok(mut scratchcards) = input.parse::<PileOfScratchcards>() {
+                for (line, reason) in scratchcards.dropped.iter() {
+                    println!("dropped line {}: {}", line, reason);
+                }
+                for problem in scratchcards.check_order() {
+                    println!("order problem: {}", problem);
+                }
+                if std::env::args().any(|arg| arg == "--reindex") {
+                    scratchcards.reindex();
+                }
+                let past_end = if std::env::args().any(|arg| arg == "--past-end=error") {
+                    PastEnd::Error
+                } else if std::env::args().any(|arg| arg == "--past-end=clamp") {
+                    PastEnd::Clamp
+                } else {
+                    PastEnd::Ignore
+                };
+                if std::env::args().any(|arg| arg == "--validate") {
+                    for (id, numbers) in scratchcards.duplicated_numbers() {
+                        println!("card {} lists {:?} more than once", id, numbers);
+                    }
+                }
+                let mut scoring: Box<dyn ScoringRule> = Box::new(Doubling);
+                let mut cascade: Box<dyn CascadeRule> = Box::new(Factor(1));
+                for arg in std::env::args().skip(1) {
+                    if let Some(name) = arg.strip_prefix("--scoring=") {
+                        match scoring_rule(name) {
+                            Some(rule) => scoring = rule,
+                            None => { println!("error = unknown scoring rule {}", name); return }
+                        }
+                    } else if arg == "--copies=tapering" {
+                        cascade = Box::new(Tapering);
+                    } else if let Some(factor) = arg.strip_prefix("--copies=") {
+                        match factor.parse::<u128>() {
+                            Ok(factor) => cascade = Box::new(Factor(factor)),
+                            Err(reason) => { println!("error = {}", reason); return }
+                        }
+                    }
+                }
+
+                println!("points = {}", scratchcards.count_points(scoring.as_ref()));
+                match scratchcards.count_cards_part_2(past_end, cascade.as_ref()) {
+                    Ok(cards) => println!("cards = {}", cards),
+                    Err(reason) => println!("error = {}", reason),
+                }
+                if let Ok(CascadeTrace { most_descendants: Some((id, descendants)), .. }) = scratchcards.trace_cascade_with(past_end, cascade.as_ref()) {
+                    println!("card {} produced the most copies: {}", id, descendants);
+                }
             }
         },
         Err(reason) => println!("error = {}", reason)
@@ -142,27 +398,167 @@ pub fn main() {
 mod tests {
     use super::*;
 
-    #[test]
-    fn count_points_example() {
-        static DATA: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
+    static DATA: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
 Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
 Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
 Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
 Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
 Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
+
+    /// Two cards winning copies, and a last one that doesn't.
+    static CASCADE: &str = "Card 1: 1 2 | 1 2
+Card 2: 1 | 1
+Card 3: 4 | 5";
+
+    #[test]
+    fn count_points_example() {
         let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
-        assert_eq!(scratchcards.count_points(), 13);
+        assert_eq!(scratchcards.count_points(&Doubling), 13);
     }
 
     #[test]
     fn count_cards_part_2() {
-        static DATA: &str = "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53
-Card 2: 13 32 20 16 61 | 61 30 68 82 17 32 24 19
-Card 3:  1 21 53 59 44 | 69 82 63 72 16 21 14  1
-Card 4: 41 92 73 84 69 | 59 84 76 51 58  5 54 83
-Card 5: 87 83 26 28 32 | 88 30 70 12 93 22 82 36
-Card 6: 31 18 13 56 72 | 74 77 10 23 35 67 36 11";
         let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
-        assert_eq!(scratchcards.count_cards_part_2(), 30);
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(1)), Ok(30));
+    }
+
+    #[test]
+    fn trace_cascade_example() {
+        let trace = DATA.parse::<PileOfScratchcards>().unwrap().trace_cascade_with(PastEnd::Ignore, &Factor(1)).unwrap();
+        assert_eq!(trace.copies, vec![1, 2, 4, 8, 14, 1]);
+        assert_eq!(trace.contributions[0], vec![(2, 1), (3, 1), (4, 1), (5, 1)]);
+        assert_eq!(trace.contributions[2], vec![(4, 4), (5, 4)]);
+        assert!(trace.contributions[5].is_empty());
+        // Card 1 wins 2, 3, 4 and 5, which win 2 + 2 + 1 + 0 copies with 1 + 1 + 0 more.
+        assert_eq!(trace.most_descendants, Some((1, 14)));
+    }
+
+    #[test]
+    fn trace_cascade_overflow() {
+        // Every card wins copies of the next ten, which grows faster than u128 can count.
+        let data: String = (1..=200).map(|id| format!("Card {}: 1 2 3 4 5 6 7 8 9 10 | 1 2 3 4 5 6 7 8 9 10\n", id)).collect();
+        let scratchcards = data.parse::<PileOfScratchcards>().unwrap();
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(1)), Err("too many cards"));
+
+        let data: String = (1..=100).map(|id| format!("Card {}: 1 2 | 1 2\n", id)).collect();
+        let trace = data.parse::<PileOfScratchcards>().unwrap().trace_cascade_with(PastEnd::Ignore, &Factor(1)).unwrap();
+        // 1 + copies of the two cards before, past u64 already.
+        assert_eq!(trace.copies[99], 927372692193078999175);
+    }
+
+    #[test]
+    fn count_winning_ignores_duplicates() {
+        let card = "Card 1: 41 48 41 17 | 41 17 9 17".parse::<Scratchcard>().unwrap();
+        assert_eq!(card.count_winning(), 2);
+        assert_eq!(card.duplicates, vec![41, 17]);
+    }
+
+    #[test]
+    fn duplicated_numbers() {
+        static DATA: &str = "Card 1: 41 48 83 | 83 86 6
+Card 2: 13 13 20 | 61 30 61
+Card 3:  1 21 53 | 69 82 63";
+        let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        assert_eq!(scratchcards.duplicated_numbers(), vec![(2, &[13, 61][..])]);
+    }
+
+    #[test]
+    fn dropped_lines() {
+        static DATA: &str = "Card 1: 41 48 | 83 86
+Card x: 13 32 | 61 30
+
+Card 3: 1 21 | 69 a2
+Card 4: 41 92 | 59 84";
+        let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        assert_eq!(scratchcards.cards.len(), 2);
+        assert_eq!(scratchcards.dropped, vec![(2, "invalid card id"), (4, "invalid input")]);
+    }
+
+    #[test]
+    fn check_order_problems() {
+        static DATA: &str = "Card 2: 1 | 1
+Card 3: 1 | 1
+Card 6: 1 | 1
+Card 4: 1 | 1
+Card 4: 1 | 1";
+        let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        let problems = scratchcards.check_order();
+        assert_eq!(problems, vec![
+            OrderProblem::FirstNotOne(2),
+            OrderProblem::Gap { previous: 3, id: 6 },
+            OrderProblem::OutOfOrder { previous: 6, id: 4 },
+            OrderProblem::Duplicate(4),
+        ]);
+        assert_eq!(problems[1].to_string(), "cards 4 to 5 are missing");
+    }
+
+    #[test]
+    fn reindex_unsorted_with_gaps() {
+        static DATA: &str = "Card 30: 1 2 | 3 4
+Card 10: 1 2 | 1 2
+Card 20: 1 2 | 1 3";
+        let mut scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(1)), Ok(3));
+        scratchcards.reindex();
+        assert!(scratchcards.check_order().is_empty());
+        // Card 1 wins 2 and 3, both cards 2 win another 3.
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(1)), Ok(1 + 2 + 4));
+    }
+
+    #[test]
+    fn past_end_policies() {
+        static DATA: &str = "Card 1: 1 2 | 1 2
+Card 2: 1 2 3 | 1 2 3
+Card 3: 4 | 5";
+        let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(1)), Ok(1 + 2 + 4));
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Error, &Factor(1)), Err("cascade runs past the last card"));
+        // Card 2 wins 3, 4 and 5, which all become copies of card 3.
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Clamp, &Factor(1)), Ok(1 + 2 + (1 + 1 + 2 * 3)));
+    }
+
+    #[test]
+    fn scoring_rules() {
+        assert_eq!((0..6).map(|w| Doubling.points(w)).collect::<Vec<_>>(), vec![0, 1, 2, 4, 8, 16]);
+        assert_eq!((0..6).map(|w| Linear.points(w)).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!((0..6).map(|w| Fibonacci.points(w)).collect::<Vec<_>>(), vec![0, 1, 1, 2, 3, 5]);
+        let table = scoring_rule("table:0, 10, 15").unwrap();
+        assert_eq!((0..5).map(|w| table.points(w)).collect::<Vec<_>>(), vec![0, 10, 15, 15, 15]);
+        assert_eq!(Doubling.points(100), u64::MAX);
+        assert!(scoring_rule("table:1,x").is_none());
+        assert!(scoring_rule("quadratic").is_none());
+    }
+
+    #[test]
+    fn count_points_with_rules() {
+        let scratchcards = DATA.parse::<PileOfScratchcards>().unwrap();
+        // 4, 2, 2, 1, 0 and 0 winning numbers.
+        assert_eq!(scratchcards.count_points(&Linear), 9);
+        assert_eq!(scratchcards.count_points(&Fibonacci), 3 + 1 + 1 + 1);
+        assert_eq!(scratchcards.count_points(scoring_rule("doubling").unwrap().as_ref()), 13);
+    }
+
+    #[test]
+    fn cascade_factor() {
+        let scratchcards = CASCADE.parse::<PileOfScratchcards>().unwrap();
+        let trace = scratchcards.trace_cascade_with(PastEnd::Ignore, &Factor(3)).unwrap();
+        // Card 1 gives 3 copies each to 2 and 3, the 4 cards 2 give 3 each to 3.
+        assert_eq!(trace.copies, vec![1, 4, 1 + 3 + 12]);
+        assert_eq!(trace.contributions[1], vec![(3, 12)]);
+        assert_eq!(trace.most_descendants, Some((1, 3 + 3 + 3 * 3)));
+        assert_eq!(scratchcards.trace_cascade_with(PastEnd::Ignore, &Factor(1)).unwrap().copies, vec![1, 2, 4]);
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Factor(3)), Ok(1 + 4 + 16));
+    }
+
+    #[test]
+    fn cascade_tapering() {
+        let scratchcards = CASCADE.parse::<PileOfScratchcards>().unwrap();
+        let trace = scratchcards.trace_cascade_with(PastEnd::Ignore, &Tapering).unwrap();
+        // Card 1 gives 2 copies to card 2 and 1 to card 3, the 3 cards 2 give 1 each to 3.
+        assert_eq!(trace.copies, vec![1, 3, 1 + 1 + 3]);
+        assert_eq!(trace.contributions[0], vec![(2, 2), (3, 1)]);
+        // The original card 1: 2 copies of card 2 (each giving another card 3) and 1 card 3.
+        assert_eq!(trace.most_descendants, Some((1, 2 * (1 + 1) + 1)));
+        assert_eq!(scratchcards.count_cards_part_2(PastEnd::Ignore, &Tapering), Ok(1 + 3 + 5));
     }
 }
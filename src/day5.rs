@@ -1,6 +1,6 @@
 extern crate muldiv;
 use muldiv::MulDiv;
-use std::{collections::HashMap, cmp::min};
+use std::{collections::{BTreeMap, HashMap, HashSet}, cmp::min, fmt};
 
 #[derive(Debug)]
 struct AlmanacRange {
@@ -18,13 +18,49 @@ impl AlmanacRange {
         if key < self.source_min {
             return None;
         }
-        if key >= self.source_min + self.length {
+        if key >= self.source_end() {
             return None;
         }
 
         let result = self.destination_min + (key - self.source_min);
         Some(result)
     }
+
+    /// The length cut off where the source or the destination range would run past
+    /// `usize::MAX`. All lookups only use this part of the range.
+    fn usable_length(&self) -> usize {
+        min(self.length, min(usize::MAX - self.source_min, usize::MAX - self.destination_min))
+    }
+
+    fn source_end(&self) -> usize {
+        self.source_min + self.usable_length()
+    }
+
+    /// Whether the source or the destination range runs past `usize::MAX`.
+    fn overflows(&self) -> bool {
+        self.source_min.checked_add(self.length).is_none() || self.destination_min.checked_add(self.length).is_none()
+    }
+}
+
+/// Something `AlmanacMap::normalise` found wrong with the ranges of a map, by the
+/// index of the range in the file.
+#[derive(Debug, PartialEq)]
+enum RangeProblem {
+    Empty { map: String, range: usize },
+    Overflow { map: String, range: usize },
+    /// Keys in `start..end` are in both ranges; `first` wins.
+    Overlap { map: String, first: usize, second: usize, start: usize, end: usize },
+}
+
+impl fmt::Display for RangeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RangeProblem::Empty { map, range } => write!(f, "{}: range {} is empty", map, range + 1),
+            RangeProblem::Overflow { map, range } => write!(f, "{}: range {} runs past {}", map, range + 1, usize::MAX),
+            RangeProblem::Overlap { map, first, second, start, end } =>
+                write!(f, "{}: range {} hides range {} for {}..{}", map, first + 1, second + 1, start, end),
+        }
+    }
 }
 
 /// A mapping in the almanac.
@@ -45,12 +81,303 @@ impl AlmanacMap {
 
     fn lookup(&self, key: usize) -> usize {
         // println!("{}::lookup({})", self.name, key);
-        for range in &self.ranges {
+        self.lookup_range(key).0
+    }
+
+    /// Like `lookup`, but also returns the index of the range that was applied, if any.
+    fn lookup_range(&self, key: usize) -> (usize, Option<usize>) {
+        for (index, range) in self.ranges.iter().enumerate() {
             if let Some(mapped) = range.lookup(key) {
-                return mapped;
+                return (mapped, Some(index));
+            }
+        }
+        (key, None)
+    }
+
+    /// Maps all keys in `[start, end)` at once, as the intervals they end up in. Like
+    /// `lookup` the first matching range wins, and keys outside all ranges stay as they are.
+    fn lookup_interval(&self, start: usize, end: usize) -> Vec<(usize, usize)> {
+        let mut result = vec![];
+        let mut pending = vec![(start, end)];
+        for range in &self.ranges {
+            let source_end = range.source_end();
+            let mut outside = vec![];
+            for (start, end) in pending {
+                let overlap_start = std::cmp::max(start, range.source_min);
+                let overlap_end = std::cmp::min(end, source_end);
+                if overlap_start >= overlap_end {
+                    outside.push((start, end));
+                    continue;
+                }
+                result.push((range.destination_min + (overlap_start - range.source_min), range.destination_min + (overlap_end - range.source_min)));
+                if start < overlap_start {
+                    outside.push((start, overlap_start));
+                }
+                if overlap_end < end {
+                    outside.push((overlap_end, end));
+                }
             }
+            pending = outside;
+        }
+        result.extend(pending);
+        result
+    }
+
+    /// The same mapping as sorted ranges that cover `0..usize::MAX` without overlapping,
+    /// with the keys that map to themselves as explicit ranges. Ranges that overflow are
+    /// cut off at `usize::MAX`.
+    fn normalise(&self) -> (AlmanacMap, Vec<RangeProblem>) {
+        let mut problems = vec![];
+        let mut clamped = AlmanacMap::new(&self.name);
+        for (index, range) in self.ranges.iter().enumerate() {
+            if range.length == 0 {
+                problems.push(RangeProblem::Empty { map: self.name.clone(), range: index });
+                continue;
+            }
+            if range.overflows() {
+                problems.push(RangeProblem::Overflow { map: self.name.clone(), range: index });
+            }
+            clamped.append_range(range.destination_min, range.source_min, range.usable_length());
+        }
+
+        let mut by_source: Vec<usize> = (0..self.ranges.len()).filter(|i| self.ranges[*i].length > 0).collect();
+        by_source.sort_by_key(|i| (self.ranges[*i].source_min, *i));
+        for (position, &a) in by_source.iter().enumerate() {
+            for &b in &by_source[position + 1..] {
+                let (first, second) = (min(a, b), std::cmp::max(a, b));
+                let start = self.ranges[b].source_min;
+                let end = min(self.ranges[a].source_end(), self.ranges[b].source_end());
+                if start >= self.ranges[a].source_end() {
+                    break;
+                }
+                problems.push(RangeProblem::Overlap { map: self.name.clone(), first, second, start, end });
+            }
+        }
+
+        let mut normalised = AlmanacMap::new(&self.name);
+        for (start, end, destination) in PiecewiseMap::from_map(&clamped).pieces {
+            normalised.append_range(destination, start, end - start);
+        }
+        (normalised, problems)
+    }
+}
+
+impl fmt::Display for AlmanacMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} map:", self.name)?;
+        for range in &self.ranges {
+            writeln!(f, "{} {} {}", range.destination_min, range.source_min, range.length)?;
+        }
+        Ok(())
+    }
+}
+
+/// A mapping from every key to a value, as sorted pieces `(source_start, source_end,
+/// destination_start)` that together cover `0..usize::MAX` without overlapping.
+#[derive(Debug, Clone, PartialEq)]
+struct PiecewiseMap {
+    pieces: Vec<(usize, usize, usize)>,
+}
+
+impl PiecewiseMap {
+    fn identity() -> PiecewiseMap {
+        PiecewiseMap { pieces: vec![(0, usize::MAX, 0)] }
+    }
+
+    /// The same function as `m.lookup`.
+    fn from_map(m: &AlmanacMap) -> PiecewiseMap {
+        let mut pieces = vec![];
+        let mut pending = vec![(0, usize::MAX)];
+        for range in &m.ranges {
+            let source_end = range.source_end();
+            let mut outside = vec![];
+            for (start, end) in pending {
+                let overlap_start = std::cmp::max(start, range.source_min);
+                let overlap_end = std::cmp::min(end, source_end);
+                if overlap_start >= overlap_end {
+                    outside.push((start, end));
+                    continue;
+                }
+                pieces.push((overlap_start, overlap_end, range.destination_min + (overlap_start - range.source_min)));
+                if start < overlap_start {
+                    outside.push((start, overlap_start));
+                }
+                if overlap_end < end {
+                    outside.push((overlap_end, end));
+                }
+            }
+            pending = outside;
+        }
+        pieces.extend(pending.into_iter().map(|(start, end)| (start, end, start)));
+        pieces.sort();
+        PiecewiseMap { pieces }.merged()
+    }
+
+    /// Joins neighbouring pieces that continue each other.
+    fn merged(self) -> PiecewiseMap {
+        let mut pieces: Vec<(usize, usize, usize)> = vec![];
+        for piece in self.pieces {
+            if let Some(last) = pieces.last_mut() {
+                if last.1 == piece.0 && last.2 + (last.1 - last.0) == piece.2 {
+                    last.1 = piece.1;
+                    continue;
+                }
+            }
+            pieces.push(piece);
+        }
+        PiecewiseMap { pieces }
+    }
+
+    fn lookup(&self, key: usize) -> usize {
+        let index = self.pieces.partition_point(|(start, _, _)| *start <= key);
+        match index.checked_sub(1).map(|index| self.pieces[index]) {
+            Some((start, end, destination)) if key < end => destination + (key - start),
+            _ => key,
+        }
+    }
+
+    /// `next` applied after `self`.
+    fn then(&self, next: &PiecewiseMap) -> PiecewiseMap {
+        let mut pieces = vec![];
+        for (start, end, destination) in self.pieces.iter() {
+            let destination_end = destination + (end - start);
+            let first = next.pieces.partition_point(|(_, next_end, _)| *next_end <= *destination);
+            for (next_start, next_end, next_destination) in next.pieces[first..].iter() {
+                if *next_start >= destination_end {
+                    break;
+                }
+                let overlap_start = std::cmp::max(*destination, *next_start);
+                let overlap_end = std::cmp::min(destination_end, *next_end);
+                pieces.push((start + (overlap_start - destination), start + (overlap_end - destination), next_destination + (overlap_start - next_start)));
+            }
+        }
+        PiecewiseMap { pieces }.merged()
+    }
+
+    fn inverse(&self) -> InverseMap {
+        // Cut the destinations into intervals where the same pieces map to them.
+        let mut cuts: Vec<usize> = self.pieces.iter().flat_map(|(start, end, destination)| [*destination, destination + (end - start)]).collect();
+        cuts.sort();
+        cuts.dedup();
+
+        let mut by_destination: Vec<&(usize, usize, usize)> = self.pieces.iter().collect();
+        by_destination.sort_by_key(|(_, _, destination)| *destination);
+
+        let mut pieces = vec![];
+        for cut in cuts.windows(2) {
+            let (start, end) = (cut[0], cut[1]);
+            let sources: Vec<usize> = by_destination.iter()
+                .take_while(|(_, _, destination)| *destination <= start)
+                .filter(|(source_start, source_end, destination)| start < destination + (source_end - source_start))
+                .map(|(source_start, _, destination)| source_start + (start - destination))
+                .collect();
+            if !sources.is_empty() {
+                pieces.push((start, end, sources));
+            }
+        }
+        InverseMap { pieces }
+    }
+}
+
+/// All keys that a `PiecewiseMap` maps to a value, as sorted pieces `(start, end, sources)`
+/// where `sources` are the keys for `start`.
+#[derive(Debug)]
+struct InverseMap {
+    pieces: Vec<(usize, usize, Vec<usize>)>,
+}
+
+impl InverseMap {
+    fn lookup(&self, value: usize) -> Vec<usize> {
+        let index = self.pieces.partition_point(|(start, _, _)| *start <= value);
+        match index.checked_sub(1).map(|index| &self.pieces[index]) {
+            Some((start, end, sources)) if value < *end => {
+                let mut result: Vec<usize> = sources.iter().map(|source| source + (value - start)).collect();
+                result.sort();
+                result
+            },
+            _ => vec![],
+        }
+    }
+}
+
+/// One step from a seed to its location: the category reached, its value, and the
+/// index of the range in `map` that was applied (`None` if the value passed through).
+#[derive(Debug, PartialEq)]
+struct Hop {
+    map: String,
+    category: String,
+    value: usize,
+    range: Option<usize>,
+}
+
+/// How a seed ends up at its location.
+#[derive(Debug, PartialEq)]
+struct Explanation {
+    seed: usize,
+    hops: Vec<Hop>,
+}
+
+impl Explanation {
+    fn location(&self) -> usize {
+        self.hops.last().map(|hop| hop.value).unwrap_or(self.seed)
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seed {}", self.seed)?;
+        for hop in &self.hops {
+            match hop.range {
+                Some(range) => write!(f, " -> {} {} ({} range {})", hop.category, hop.value, hop.map, range + 1)?,
+                None => write!(f, " -> {} {} (unmapped)", hop.category, hop.value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PathError {
+    NoPath { from: String, to: String },
+    /// More than one way leads from `from` to `to`; two of them, listed by map name.
+    Ambiguous { from: String, to: String, paths: Vec<Vec<String>> },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::NoPath { from, to } => write!(f, "no maps lead from {} to {}", from, to),
+            PathError::Ambiguous { from, to, paths } => {
+                write!(f, "more than one way leads from {} to {}:", from, to)?;
+                for path in paths {
+                    write!(f, " [{}]", path.join(", "))?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Why the lowest location can't be found.
+#[derive(Debug, PartialEq)]
+enum LocationError {
+    Path(PathError),
+    /// Seed ranges come in pairs of start and length, this many seeds don't.
+    OddSeedCount(usize),
+}
+
+impl From<PathError> for LocationError {
+    fn from(error: PathError) -> Self {
+        LocationError::Path(error)
+    }
+}
+
+impl fmt::Display for LocationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LocationError::Path(error) => write!(f, "{}", error),
+            LocationError::OddSeedCount(count) => write!(f, "{} seeds can't be paired up into ranges", count),
         }
-        key
     }
 }
 
@@ -67,14 +394,14 @@ impl std::str::FromStr for Almanac {
         let lines = s.lines().collect::<Vec<&str>>();
 
         // First line: seeds.
-        let seeds_line = lines[0].split_once(':');
-        if seeds_line.is_none() {
+        let Some((seeds_keyword, seeds_line)) = lines[0].split_once(':') else {
             return Err("invalid line: no delimiter");
-        } else if seeds_line.unwrap().0 != "seeds" {
+        };
+        if seeds_keyword != "seeds" {
             return Err("invalid line: no seeds keyword");
         }
-        println!("parsing seeds {}", seeds_line.unwrap().1);
-        let seeds = seeds_line.unwrap().1
+        println!("parsing seeds {}", seeds_line);
+        let seeds = seeds_line
             .split(' ')
             .filter_map(|s| s.parse::<usize>().ok()).collect::<Vec<usize>>();
 
@@ -85,12 +412,14 @@ impl std::str::FromStr for Almanac {
         for line in &lines[1..] {
             if line.trim().is_empty() {
                 if let Some(m) = map.take() {
-                    maps.insert(m.name.clone(), m);
+                    if maps.insert(m.name.clone(), m).is_some() {
+                        return Err("invalid almanac: duplicate map");
+                    }
                 }
                 continue
             }
 
-            if map.is_some() {
+            if let Some(map) = map.as_mut() {
                 let parts = line.split(' ').collect::<Vec<&str>>();
                 if parts.len() != 3 {
                     return Err("invalid map line");
@@ -98,73 +427,158 @@ impl std::str::FromStr for Almanac {
                 let destination_min = parts[0].parse::<usize>().unwrap();
                 let source_min = parts[1].parse::<usize>().unwrap();
                 let length = parts[2].parse::<usize>().unwrap();
-                map.as_mut().unwrap().append_range( destination_min, source_min, length);
+                map.append_range( destination_min, source_min, length);
             } else if let Some((name, map_keyword)) = line.split_once(' ') {
                 if map_keyword != "map:" {
                     return Err("invalid line: no map keyword");
                 }
+                if !name.contains("-to-") {
+                    return Err("invalid map name: no -to-");
+                }
                 map = Some(AlmanacMap::new(name));
             } else {
                 return Err("invalid line: expected a map");
             }
         }
         if let Some(m) = map.take() {
-            maps.insert(m.name.clone(), m);
+            if maps.insert(m.name.clone(), m).is_some() {
+                return Err("invalid almanac: duplicate map");
+            }
         }
         Ok(Almanac { seeds, maps })
     }
 }
 
 impl Almanac {
-    fn find_map(&self, from: &str) -> Option<(&str, &AlmanacMap)> {
+    /// The maps leaving each category, by the category they lead to.
+    fn graph(&self) -> BTreeMap<&str, Vec<(&str, &AlmanacMap)>> {
+        let mut graph: BTreeMap<&str, Vec<(&str, &AlmanacMap)>> = BTreeMap::new();
         for m in self.maps.values() {
             if let Some((map_from, map_to)) = m.name.split_once("-to-") {
-                if map_from == from {
-                    return Some((map_to, m))
-                }
+                graph.entry(map_from).or_default().push((map_to, m));
             }
         }
-        None
+        for edges in graph.values_mut() {
+            edges.sort_by_key(|(map_to, _)| *map_to);
+        }
+        graph
     }
 
-    fn mapping<'a>(&'a self, from: &'a str, to: &'a str) -> impl Fn(usize) -> usize + 'a  {
-        let mut next_from = from;
-        let mut maps = vec![];
+    /// The maps to apply, in order, to get from `from` to `to`. There must be exactly
+    /// one way through the category graph.
+    fn path(&self, from: &str, to: &str) -> Result<Vec<&AlmanacMap>, PathError> {
+        let graph = self.graph();
 
-        while next_from != to {
-            println!("mapping: {} -> {}", next_from, to);
-            if let Some((map_to, m)) = self.find_map(next_from) {
-                // This is the next map to follow
-                maps.push(m);
-                next_from = map_to;
+        // Categories that `to` can be reached from, so the search never walks into a
+        // dead end.
+        let mut reaching = HashSet::from([to]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (map_from, edges) in graph.iter() {
+                if !reaching.contains(map_from) && edges.iter().any(|(map_to, _)| reaching.contains(map_to)) {
+                    reaching.insert(map_from);
+                    changed = true;
+                }
             }
         }
 
-        move |v| {
+        let mut paths = vec![];
+        let mut current = vec![];
+        let mut visited = vec![from];
+        find_paths(&graph, &reaching, from, to, &mut visited, &mut current, &mut paths);
+        match paths.len() {
+            0 => Err(PathError::NoPath { from: from.to_string(), to: to.to_string() }),
+            1 => Ok(paths.remove(0)),
+            _ => Err(PathError::Ambiguous {
+                from: from.to_string(),
+                to: to.to_string(),
+                paths: paths.iter().map(|path| path.iter().map(|m| m.name.clone()).collect()).collect(),
+            }),
+        }
+    }
+
+    fn mapping<'a>(&'a self, from: &str, to: &str) -> Result<impl Fn(usize) -> usize + 'a, PathError> {
+        let maps = self.path(from, to)?;
+        Ok(move |v| {
             let mut result = v;
             for m in maps.iter() {
                 result = m.lookup(result)
             }
             result
+        })
+    }
+
+    /// The maps from `from` to `to` composed into a single function.
+    fn compose(&self, from: &str, to: &str) -> Result<PiecewiseMap, PathError> {
+        Ok(self.path(from, to)?.iter().fold(PiecewiseMap::identity(), |composed, m| composed.then(&PiecewiseMap::from_map(m))))
+    }
+
+    fn explain(&self, seed: usize) -> Result<Explanation, PathError> {
+        let mut hops = vec![];
+        let mut value = seed;
+        for m in self.path("seed", "location")? {
+            let (mapped, range) = m.lookup_range(value);
+            let category = m.name.split_once("-to-").map(|(_, to)| to).unwrap_or(&m.name);
+            hops.push(Hop { map: m.name.clone(), category: category.to_string(), value: mapped, range });
+            value = mapped;
+        }
+        Ok(Explanation { seed, hops })
+    }
+
+    /// Explains the lowest location, taking the seeds one by one or as ranges like
+    /// `lowest_location`. `None` if there are no seeds.
+    fn explain_lowest_location(&self, use_ranges: bool) -> Result<Option<Explanation>, LocationError> {
+        let composed = self.compose("seed", "location")?;
+        let intervals: Vec<(usize, usize)> = if use_ranges {
+            self.seed_ranges()?
+        } else {
+            self.seeds.iter().map(|seed| (*seed, seed.saturating_add(1))).collect()
+        };
+
+        // Within a piece the location grows with the seed, so only the first seed of
+        // each overlap between an interval and a piece can be the lowest.
+        let mut best: Option<(usize, usize)> = None;
+        for (start, end) in intervals {
+            let first = composed.pieces.partition_point(|(_, piece_end, _)| *piece_end <= start);
+            for (piece_start, _, destination) in &composed.pieces[first..] {
+                if *piece_start >= end {
+                    break;
+                }
+                let seed = std::cmp::max(start, *piece_start);
+                let location = destination + (seed - piece_start);
+                if best.is_none_or(|best| (location, seed) < best) {
+                    best = Some((location, seed));
+                }
+            }
+        }
+        Ok(best.map(|(_, seed)| self.explain(seed)).transpose()?)
+    }
+
+    /// The seeds as non-empty `[start, end)` ranges. Ranges that would run past
+    /// `usize::MAX` are cut off there, like the ranges of the maps.
+    fn seed_ranges(&self) -> Result<Vec<(usize, usize)>, LocationError> {
+        if !self.seeds.len().is_multiple_of(2) {
+            return Err(LocationError::OddSeedCount(self.seeds.len()));
         }
+        Ok(self.seeds.chunks_exact(2)
+            .map(|pair| (pair[0], pair[0].saturating_add(pair[1])))
+            .filter(|(start, end)| start < end)
+            .collect())
     }
 
-    fn lowest_location(&self, use_ranges: bool) -> usize {
+    fn lowest_location(&self, use_ranges: bool) -> Result<usize, LocationError> {
         // First we need to find a "path" from a "seed-to-" map
         // to a "-to-location" map. We can then apply the same path
         // for each of the seeds, and output the minimum location value.
-        let mut result = std::usize::MAX;
-        let map = self.mapping("seed", "location");
+        let mut result = usize::MAX;
+        let map = self.mapping("seed", "location")?;
         if use_ranges {
-            let mut i = 0;
-            while i < self.seeds.len() {
-                let start = self.seeds[i];
-                let length = self.seeds[i+1];
-                i += 2;
-
+            for (start, end) in self.seed_ranges()? {
+                let length = end - start;
                 for j in 0..length {
                     if j % 1000 == 0 {
-                        print!("\rscanning range {}..{}: {}%", start, start + length, (j as u64).mul_div_floor(100, length as u64).unwrap());
+                        print!("\rscanning range {}..{}: {}%", start, end, (j as u64).mul_div_floor(100, length as u64).unwrap());
                     }
                     result = min(result, map(start + j));
                 }
@@ -175,7 +589,66 @@ impl Almanac {
                 result = min(result, map(*i));
             }
         }
-        result
+        Ok(result)
+    }
+
+    /// Every map normalised, with the problems found along the way.
+    fn normalise(&self) -> (Almanac, Vec<RangeProblem>) {
+        let mut maps = HashMap::new();
+        let mut problems = vec![];
+        for name in self.maps.keys().collect::<std::collections::BTreeSet<_>>() {
+            let (normalised, map_problems) = self.maps[name].normalise();
+            maps.insert(name.clone(), normalised);
+            problems.extend(map_problems);
+        }
+        (Almanac { seeds: self.seeds.clone(), maps }, problems)
+    }
+
+    /// Like `lowest_location(true)`, but pushes the seed ranges through the maps as
+    /// intervals instead of looking up every single seed.
+    fn lowest_location_intervals(&self) -> Result<usize, LocationError> {
+        let mut intervals = self.seed_ranges()?;
+        for m in self.path("seed", "location")? {
+            intervals = intervals.iter().flat_map(|(start, end)| m.lookup_interval(*start, *end)).collect();
+        }
+        Ok(intervals.iter().map(|(start, _)| *start).min().unwrap_or(usize::MAX))
+    }
+}
+
+impl fmt::Display for Almanac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "seeds:")?;
+        for seed in &self.seeds {
+            write!(f, " {}", seed)?;
+        }
+        writeln!(f)?;
+        for name in self.maps.keys().collect::<std::collections::BTreeSet<_>>() {
+            write!(f, "\n{}", self.maps[name])?;
+        }
+        Ok(())
+    }
+}
+
+/// Depth-first search for paths from `from` to `to` through `reaching` that visit each
+/// category at most once. Stops at the second path, which is enough to tell that there
+/// is more than one.
+fn find_paths<'a: 'v, 'v>(graph: &BTreeMap<&'a str, Vec<(&'a str, &'a AlmanacMap)>>, reaching: &HashSet<&str>, from: &str, to: &str, visited: &mut Vec<&'v str>, current: &mut Vec<&'a AlmanacMap>, paths: &mut Vec<Vec<&'a AlmanacMap>>) {
+    if from == to {
+        paths.push(current.clone());
+        return;
+    }
+    for (map_to, m) in graph.get(from).into_iter().flatten() {
+        if paths.len() >= 2 {
+            return;
+        }
+        if visited.contains(map_to) || !reaching.contains(map_to) {
+            continue;
+        }
+        visited.push(map_to);
+        current.push(m);
+        find_paths(graph, reaching, map_to, to, visited, current, paths);
+        current.pop();
+        visited.pop();
     }
 }
 
@@ -183,8 +656,44 @@ pub fn main() {
     match std::fs::read_to_string("day5.input") {
         Ok(input) => {
             if let Ok(almanac) = input.parse::<Almanac>() {
-                println!("lowest_location (part 1) = {}", almanac.lowest_location(false));
-                println!("lowest_location (part 2) = {}", almanac.lowest_location(true));
+                match almanac.lowest_location(false) {
+                    Ok(location) => println!("lowest_location (part 1) = {}", location),
+                    Err(error) => println!("error = {}", error),
+                }
+                match almanac.lowest_location_intervals() {
+                    Ok(location) => println!("lowest_location (part 2) = {}", location),
+                    Err(error) => println!("error = {}", error),
+                }
+                for arg in std::env::args().skip(1) {
+                    if arg == "--explain" {
+                        for use_ranges in [false, true] {
+                            match almanac.explain_lowest_location(use_ranges) {
+                                Ok(Some(explanation)) => println!("lowest_location = {}: {}", explanation.location(), explanation),
+                                Ok(None) => println!("no seeds"),
+                                Err(error) => println!("error = {}", error),
+                            }
+                        }
+                    }
+                    if arg == "--normalise" {
+                        let (normalised, problems) = almanac.normalise();
+                        for problem in problems {
+                            println!("problem: {}", problem);
+                        }
+                        print!("{}", normalised);
+                    }
+                    if let Some(location) = arg.strip_prefix("--seeds-for-location=").and_then(|l| l.parse::<usize>().ok()) {
+                        let composed = match almanac.compose("seed", "location") {
+                            Ok(composed) => composed,
+                            Err(error) => {
+                                println!("error = {}", error);
+                                continue;
+                            }
+                        };
+                        let lowest = almanac.seeds.iter().map(|seed| composed.lookup(*seed)).min();
+                        println!("lowest_location (part 1, composed) = {:?}", lowest);
+                        println!("seeds for location {} = {:?}", location, composed.inverse().lookup(location));
+                    }
+                }
             }
         },
         Err(reason) => println!("error = {}", reason)
@@ -232,12 +741,351 @@ humidity-to-location map:
     #[test]
     fn part1_example() {
         let almanac = DATA.parse::<Almanac>().unwrap();
-        assert_eq!(almanac.lowest_location(false), 35);
+        assert_eq!(almanac.lowest_location(false), Ok(35));
     }
 
     #[test]
     fn part2_example() {
         let almanac = DATA.parse::<Almanac>().unwrap();
-        assert_eq!(almanac.lowest_location(true), 46);
+        assert_eq!(almanac.lowest_location(true), Ok(46));
+    }
+
+    #[test]
+    fn part2_example_intervals() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        assert_eq!(almanac.lowest_location_intervals(), Ok(46));
+    }
+
+    #[test]
+    fn lookup_interval_splits() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let m = &almanac.maps["seed-to-soil"];
+        let mut intervals = m.lookup_interval(45, 101);
+        intervals.sort();
+        // 45..50 unmapped, 50..98 shifted by 2, 98..100 to 50..52, 100..101 unmapped.
+        assert_eq!(intervals, vec![(45, 50), (50, 52), (52, 100), (100, 101)]);
+    }
+
+    #[test]
+    fn lookup_interval_matches_lookup() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        for m in almanac.maps.values() {
+            for start in 0..110 {
+                for length in [1, 3, 17, 60] {
+                    let mut expected: Vec<usize> = (start..start + length).map(|key| m.lookup(key)).collect();
+                    let mut mapped: Vec<usize> = m.lookup_interval(start, start + length).iter().flat_map(|(s, e)| *s..*e).collect();
+                    expected.sort();
+                    mapped.sort();
+                    assert_eq!(mapped, expected, "map = {}, start = {}, length = {}", m.name, start, length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lowest_location_intervals_matches_brute_force() {
+        let mut almanac = DATA.parse::<Almanac>().unwrap();
+        for seeds in [vec![0, 100], vec![79, 14, 55, 13], vec![13, 1, 90, 7], vec![5, 0, 40, 3]] {
+            almanac.seeds = seeds;
+            assert_eq!(almanac.lowest_location_intervals(), almanac.lowest_location(true), "seeds = {:?}", almanac.seeds);
+        }
+    }
+
+    #[test]
+    fn compose_matches_mapping() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        for (from, to) in [("seed", "location"), ("soil", "light"), ("humidity", "location"), ("seed", "seed")] {
+            let composed = almanac.compose(from, to).unwrap();
+            let mapping = almanac.mapping(from, to).unwrap();
+            for key in 0..200 {
+                assert_eq!(composed.lookup(key), mapping(key), "{} -> {}, key = {}", from, to, key);
+            }
+            assert_eq!(composed.lookup(usize::MAX - 1), mapping(usize::MAX - 1));
+        }
+        assert_eq!(almanac.compose("seed", "seed"), Ok(PiecewiseMap::identity()));
+    }
+
+    #[test]
+    fn compose_pieces_are_sorted() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let composed = almanac.compose("seed", "location").unwrap();
+        assert_eq!(composed.pieces[0].0, 0);
+        assert_eq!(composed.pieces.last().unwrap().1, usize::MAX);
+        assert!(composed.pieces.windows(2).all(|pair| pair[0].1 == pair[1].0));
+    }
+
+    #[test]
+    fn inverse_example() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let composed = almanac.compose("seed", "location").unwrap();
+        let inverse = composed.inverse();
+        assert_eq!(inverse.lookup(82), vec![79]);
+        assert_eq!(inverse.lookup(35), vec![13]);
+        for location in 0..200 {
+            let seeds = inverse.lookup(location);
+            let expected: Vec<usize> = (0..400).filter(|seed| composed.lookup(*seed) == location).collect();
+            assert_eq!(seeds.iter().filter(|seed| **seed < 400).copied().collect::<Vec<_>>(), expected, "location = {}", location);
+        }
+    }
+
+    #[test]
+    fn inverse_not_injective() {
+        let mut m = AlmanacMap::new("a-to-b");
+        m.append_range(10, 0, 5);
+        let inverse = PiecewiseMap::from_map(&m).inverse();
+        assert_eq!(inverse.lookup(12), vec![2, 12]);
+        assert_eq!(inverse.lookup(3), vec![]);
+        assert_eq!(inverse.lookup(7), vec![7]);
+    }
+
+    #[test]
+    fn path_missing() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        assert_eq!(almanac.path("location", "seed").err(), Some(PathError::NoPath { from: "location".to_string(), to: "seed".to_string() }));
+        assert_eq!(almanac.path("seed", "planet").err(), Some(PathError::NoPath { from: "seed".to_string(), to: "planet".to_string() }));
+    }
+
+    #[test]
+    fn path_branching() {
+        let input = "seeds: 1 2
+
+seed-to-soil map:
+10 0 5
+
+seed-to-colour map:
+20 0 5
+
+soil-to-location map:
+0 11 1
+
+colour-to-shade map:
+0 20 1
+";
+        let almanac = input.parse::<Almanac>().unwrap();
+        let names = |from, to| almanac.path(from, to).unwrap().iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names("seed", "location"), vec!["seed-to-soil", "soil-to-location"]);
+        assert_eq!(names("seed", "shade"), vec!["seed-to-colour", "colour-to-shade"]);
+        assert_eq!(almanac.lowest_location(false), Ok(0));
+    }
+
+    #[test]
+    fn path_ambiguous() {
+        let input = "seeds: 1
+
+seed-to-soil map:
+10 0 5
+
+seed-to-colour map:
+20 0 5
+
+soil-to-location map:
+0 11 1
+
+colour-to-location map:
+0 20 1
+";
+        let almanac = input.parse::<Almanac>().unwrap();
+        let error = almanac.path("seed", "location").err().unwrap();
+        assert_eq!(error, PathError::Ambiguous {
+            from: "seed".to_string(),
+            to: "location".to_string(),
+            paths: vec![
+                vec!["seed-to-colour".to_string(), "colour-to-location".to_string()],
+                vec!["seed-to-soil".to_string(), "soil-to-location".to_string()],
+            ],
+        });
+        assert_eq!(error.to_string(), "more than one way leads from seed to location: [seed-to-colour, colour-to-location] [seed-to-soil, soil-to-location]");
+        assert!(almanac.lowest_location_intervals().is_err());
+    }
+
+    #[test]
+    fn path_cycle() {
+        let input = "seeds: 1
+
+seed-to-soil map:
+10 0 5
+
+soil-to-seed map:
+0 10 5
+
+soil-to-location map:
+0 11 1
+";
+        let almanac = input.parse::<Almanac>().unwrap();
+        assert_eq!(almanac.path("seed", "location").unwrap().len(), 2);
+        assert_eq!(almanac.lowest_location(false), Ok(0));
+    }
+
+    #[test]
+    fn parse_map_errors() {
+        assert_eq!("seeds: 1\n\nseed-soil map:\n1 2 3\n".parse::<Almanac>().err(), Some("invalid map name: no -to-"));
+        assert_eq!("seeds: 1\n\na-to-b map:\n1 2 3\n\na-to-b map:\n1 2 3\n".parse::<Almanac>().err(), Some("invalid almanac: duplicate map"));
+    }
+
+    #[test]
+    fn normalise_example() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let (normalised, problems) = almanac.normalise();
+        assert_eq!(problems, vec![]);
+        assert_eq!(normalised.maps["seed-to-soil"].to_string(), format!("seed-to-soil map:
+0 0 50
+52 50 48
+50 98 2
+100 100 {}
+", usize::MAX - 100));
+        for m in normalised.maps.values() {
+            assert_eq!(m.ranges[0].source_min, 0);
+            assert_eq!(m.ranges.last().unwrap().source_end(), usize::MAX);
+            assert!(m.ranges.windows(2).all(|pair| pair[0].source_end() == pair[1].source_min));
+        }
+        assert_eq!(normalised.lowest_location(false), Ok(35));
+        assert_eq!(normalised.lowest_location_intervals(), Ok(46));
+    }
+
+    #[test]
+    fn normalise_round_trip() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let (normalised, _) = almanac.normalise();
+        let text = normalised.to_string();
+        assert!(text.starts_with("seeds: 79 14 55 13\n\nfertilizer-to-water map:\n"));
+        let parsed = text.parse::<Almanac>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.normalise().0.to_string(), text);
+        let mapping = almanac.mapping("seed", "location").unwrap();
+        let parsed_mapping = parsed.mapping("seed", "location").unwrap();
+        for seed in 0..200 {
+            assert_eq!(parsed_mapping(seed), mapping(seed));
+        }
+    }
+
+    #[test]
+    fn normalise_problems() {
+        let mut m = AlmanacMap::new("a-to-b");
+        m.append_range(100, 10, 10);
+        m.append_range(200, 15, 10);
+        m.append_range(300, 5, 0);
+        m.append_range(0, usize::MAX - 5, 10);
+        let (normalised, problems) = m.normalise();
+        assert_eq!(problems, vec![
+            RangeProblem::Empty { map: "a-to-b".to_string(), range: 2 },
+            RangeProblem::Overflow { map: "a-to-b".to_string(), range: 3 },
+            RangeProblem::Overlap { map: "a-to-b".to_string(), first: 0, second: 1, start: 15, end: 20 },
+        ]);
+        assert_eq!(problems[2].to_string(), "a-to-b: range 1 hides range 2 for 15..20");
+        for key in [0, 9, 10, 15, 19, 20, 24, 25, 1000, usize::MAX - 6] {
+            assert_eq!(normalised.lookup(key), m.lookup(key), "key = {}", key);
+        }
+        assert_eq!(normalised.lookup(usize::MAX - 5), 0);
+        assert_eq!(normalised.ranges.last().unwrap().source_end(), usize::MAX);
+    }
+
+    #[test]
+    fn explain_example() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let explanation = almanac.explain(79).unwrap();
+        let values: Vec<usize> = explanation.hops.iter().map(|hop| hop.value).collect();
+        assert_eq!(values, vec![81, 81, 81, 74, 78, 78, 82]);
+        let categories: Vec<&str> = explanation.hops.iter().map(|hop| hop.category.as_str()).collect();
+        assert_eq!(categories, vec!["soil", "fertilizer", "water", "light", "temperature", "humidity", "location"]);
+        assert_eq!(explanation.hops[0].range, Some(1));
+        assert_eq!(explanation.hops[1].range, None);
+        assert_eq!(explanation.location(), 82);
+        assert!(explanation.to_string().starts_with("seed 79 -> soil 81 (seed-to-soil range 2) -> fertilizer 81 (unmapped)"));
+    }
+
+    #[test]
+    fn explain_lowest_location_example() {
+        let almanac = DATA.parse::<Almanac>().unwrap();
+        let single = almanac.explain_lowest_location(false).unwrap().unwrap();
+        assert_eq!((single.seed, single.location()), (13, 35));
+        let ranges = almanac.explain_lowest_location(true).unwrap().unwrap();
+        assert_eq!((ranges.seed, ranges.location()), (82, 46));
+        let humidity = ranges.hops.iter().find(|hop| hop.category == "humidity").unwrap();
+        assert_eq!(humidity.value, 46);
+        assert_eq!(ranges.hops.last().unwrap().range, None);
+    }
+
+    #[test]
+    fn explain_lowest_location_matches_lowest_location() {
+        let mut almanac = DATA.parse::<Almanac>().unwrap();
+        for seeds in [vec![0, 100], vec![5, 3, 90, 20, 40, 1], vec![5, 0, 90, 1], vec![5, 0], vec![]] {
+            almanac.seeds = seeds;
+            for use_ranges in [false, true] {
+                let explanation = almanac.explain_lowest_location(use_ranges).unwrap();
+                let lowest = almanac.lowest_location(use_ranges).unwrap();
+                match explanation {
+                    Some(explanation) => {
+                        assert_eq!(explanation.location(), lowest);
+                        assert_eq!(almanac.mapping("seed", "location").unwrap()(explanation.seed), lowest);
+                    },
+                    None => assert_eq!(lowest, usize::MAX),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overflowing_ranges_are_cut_off() {
+        let mut m = AlmanacMap::new("seed-to-location");
+        m.append_range(usize::MAX - 2, 0, 10);
+        m.append_range(100, usize::MAX - 5, 10);
+        assert_eq!(m.lookup(1), usize::MAX - 1);
+        assert_eq!(m.lookup(5), 5);
+        assert_eq!(m.lookup(usize::MAX - 4), 101);
+        assert_eq!(m.lookup_interval(0, 10), vec![(usize::MAX - 2, usize::MAX), (2, 10)]);
+        assert_eq!(PiecewiseMap::from_map(&m).lookup(1), usize::MAX - 1);
+
+        let almanac = Almanac { seeds: vec![0, 10, 20, 1], maps: HashMap::from([(m.name.clone(), m)]) };
+        assert_eq!(almanac.lowest_location(false), Ok(10));
+        assert_eq!(almanac.lowest_location(true), Ok(2));
+        assert_eq!(almanac.lowest_location_intervals(), Ok(2));
+        assert_eq!(almanac.compose("seed", "location").unwrap().lookup(9), 9);
+        assert_eq!(almanac.normalise().1.len(), 2);
+    }
+
+    /// `count` diamonds in a row, `c0` to `c{count}`, each with two ways through it.
+    fn diamonds(count: usize) -> String {
+        let mut input = String::from("seeds: 1\n");
+        for i in 0..count {
+            for side in ["a", "b"] {
+                input += &format!("\nc{}-to-{}{} map:\n1 0 1\n\n{}{}-to-c{} map:\n0 1 1\n", i, side, i, side, i, i + 1);
+            }
+        }
+        input
+    }
+
+    #[test]
+    fn path_many_diamonds() {
+        let almanac = diamonds(40).parse::<Almanac>().unwrap();
+        let Err(PathError::Ambiguous { paths, .. }) = almanac.path("c0", "c40") else {
+            panic!("expected an ambiguous path");
+        };
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].len(), 80);
+        assert_ne!(paths[0], paths[1]);
+        assert_eq!(almanac.path("c0", "location").err(), Some(PathError::NoPath { from: "c0".to_string(), to: "location".to_string() }));
+        assert_eq!(almanac.path("c39", "c40").err().map(|error| matches!(error, PathError::Ambiguous { .. })), Some(true));
+        assert_eq!(almanac.path("a39", "c40").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn seed_ranges_past_usize_max() {
+        let mut almanac = DATA.parse::<Almanac>().unwrap();
+        almanac.seeds = vec![usize::MAX - 5, 10];
+        assert_eq!(almanac.seed_ranges(), Ok(vec![(usize::MAX - 5, usize::MAX)]));
+        assert_eq!(almanac.lowest_location_intervals(), almanac.lowest_location(true));
+        let explanation = almanac.explain_lowest_location(true).unwrap().unwrap();
+        assert_eq!(Ok(explanation.location()), almanac.lowest_location(true));
+    }
+
+    #[test]
+    fn odd_seed_count() {
+        let mut almanac = DATA.parse::<Almanac>().unwrap();
+        almanac.seeds = vec![79, 14, 55];
+        assert_eq!(almanac.lowest_location(true), Err(LocationError::OddSeedCount(3)));
+        assert_eq!(almanac.lowest_location_intervals(), Err(LocationError::OddSeedCount(3)));
+        assert_eq!(almanac.explain_lowest_location(true).err(), Some(LocationError::OddSeedCount(3)));
+        assert_eq!(LocationError::OddSeedCount(3).to_string(), "3 seeds can't be paired up into ranges");
+        assert_eq!(almanac.lowest_location(false), Ok(43));
     }
 }
@@ -1,3 +1,5 @@
+use num::{BigUint, CheckedMul, Integer, integer::Roots};
+
 struct RaceTable {
     races: Vec<(usize, usize)>,
 }
@@ -18,28 +20,40 @@ impl std::str::FromStr for RaceTable {
     }
 }
 
+/// The number of ways to hold the button for `x` out of `time` milliseconds so that the
+/// boat travels `x * (time - x) > distance` millimeters, or `None` if `time * time` does not
+/// fit into `T`.
+fn ways_to_beat<T: Integer + Roots + CheckedMul + Clone>(time: &T, distance: &T) -> Option<T> {
+    // The zeros of x^2 - time * x + distance are x{1,2} = (time +- sqrt(time^2 - 4 * distance)) / 2.
+    // The integer square root gets us within one of x1, which we then correct by checking the
+    // neighbours directly. x2 is time - x1 by symmetry.
+    let two = T::one() + T::one();
+    let square = time.checked_mul(time)?;
+    let four_distance = distance.checked_mul(&(two.clone() * two.clone()))?;
+    if square <= four_distance {
+        // Even holding the button for half the time doesn't beat the record.
+        return Some(T::zero());
+    }
+    let root = (square - four_distance).sqrt();
+    let beats = |x: &T| x.clone() * (time.clone() - x.clone()) > *distance;
+
+    let mut x1 = (time.clone() - root) / two.clone();
+    while x1 > T::zero() && beats(&(x1.clone() - T::one())) {
+        x1 = x1 - T::one();
+    }
+    while x1.clone() * two.clone() <= *time && !beats(&x1) {
+        x1 = x1 + T::one();
+    }
+    if x1.clone() * two.clone() > *time {
+        return Some(T::zero());
+    }
+    Some(time.clone() - x1 * two + T::one())
+}
+
 impl RaceTable {
     fn number_of_ways_to_beat_the_record(time: usize, distance: usize) -> usize {
-        // The distance is the record to beat, and we do that by by checking for each possible
-        // time of holding the button (x) the value of x * (time - x) > distance.
-        // Instead of running over the values, we can also find the two zeros of the function -x^2 - x*time - distance = 0: The number of
-        // times we beat the record is then floor(x2 - x1). We know that x1 is >= 0; there is a chance
-        // that x2 is > time, in which case we have to use time instead of x2.
-        //
-        // -x^2 + time * x - distance = 0 | * -1
-        //  x^2 - time * x + distance = 0
-        //
-        // The zeros of the function are x{1,2} = time/2 +- sqrt(time^2/4 - distance).
-        //
-        // The tricky part here is that if the zero is not an integer, we have to round it up (x1) or down (x2),
-        // and if it is an integer we need to exclude it from the result and use the next higher/lower value.
-        // We can do that by adding 1 and then rounding down (x1) or subtracting 1 and then rounding up (x2).
-        let m = ((time * time / 4 - distance) as f64).sqrt();
-        let x1 = ((time as f64) / 2.0 - m + 1_f64).floor().clamp(0_f64, time as f64);
-        let x2 = ((time as f64) / 2.0 + m - 1_f64).ceil().clamp(0_f64, time as f64);
-        let result = (x2 - x1 + 1_f64).floor() as usize;
-        println!("time = {}, distance = {}, m = {}, x1 = {}, x2 = {}: result = {}", time, distance, m, x1, x2, result);
-        result
+        // time * time always fits into a u128, and the result is at most time + 1.
+        ways_to_beat(&(time as u128), &(distance as u128)).unwrap() as usize
     }
 
     fn product(&self) -> usize {
@@ -51,6 +65,21 @@ impl RaceTable {
         result
     }
 
+    /// The time and distance of the single race in part 2, as written.
+    fn concatenated(&self) -> (String, String) {
+        let time = self.races.iter().map(|(time, _)| format!("{}", time)).collect::<Vec<String>>().join("");
+        let distance = self.races.iter().map(|(_, distance)| format!("{}", distance)).collect::<Vec<String>>().join("");
+        (time, distance)
+    }
+
+    /// Like `part2`, but for races of any length.
+    fn part2_big(&self) -> BigUint {
+        let (time, distance) = self.concatenated();
+        let time = time.parse::<BigUint>().unwrap();
+        let distance = distance.parse::<BigUint>().unwrap();
+        ways_to_beat(&time, &distance).unwrap()
+    }
+
     fn part2(&self) -> usize {
         let time = self.races.iter().map(|(time, _)| format!("{}", time)).collect::<Vec<String>>().join("").parse::<usize>().unwrap();
         let distance = self.races.iter().map(|(_, distance)| format!("{}", distance)).collect::<Vec<String>>().join("").parse::<usize>().unwrap();
@@ -63,7 +92,11 @@ pub fn main() {
         Ok(input) => {
             if let Ok(race_table) = input.parse::<RaceTable>() {
                 println!("product of number of ways to win races (part 1) = {}", race_table.product());
-                println!("big race (part 2) = {}", race_table.part2());
+                if std::env::args().any(|arg| arg == "--big") {
+                    println!("big race (part 2) = {}", race_table.part2_big());
+                } else {
+                    println!("big race (part 2) = {}", race_table.part2());
+                }
             }
         },
         Err(reason) => println!("error = {}", reason)
@@ -86,4 +119,67 @@ Distance:  9  40  200";
     fn test_part2() {
         assert_eq!(DATA.parse::<RaceTable>().ok().unwrap().part2(), 71503);
     }
+
+    fn brute_force(time: u64, distance: u64) -> u64 {
+        (0..=time).filter(|x| x * (time - x) > distance).count() as u64
+    }
+
+    #[test]
+    fn ways_to_beat_matches_brute_force() {
+        for time in 0..60_u64 {
+            for distance in 0..(time * time / 4 + 3) {
+                assert_eq!(ways_to_beat(&time, &distance), Some(brute_force(time, distance)), "time = {}, distance = {}", time, distance);
+            }
+        }
+    }
+
+    #[test]
+    fn unbeatable_record() {
+        assert_eq!(RaceTable::number_of_ways_to_beat_the_record(4, 4), 0);
+        assert_eq!(RaceTable::number_of_ways_to_beat_the_record(4, 100), 0);
+        assert_eq!(RaceTable::number_of_ways_to_beat_the_record(3, 2), 0);
+        assert_eq!(RaceTable::number_of_ways_to_beat_the_record(0, 0), 0);
+    }
+
+    #[test]
+    fn ways_to_beat_overflow() {
+        assert_eq!(ways_to_beat(&u128::MAX, &0), None);
+        assert_eq!(ways_to_beat(&(1_u128 << 63), &0), Some((1_u128 << 63) - 1));
+    }
+
+    /// Checks that exactly the hold times `x1..=time - x1` beat the record.
+    fn check_boundaries(time: &BigUint, distance: &BigUint, ways: &BigUint) {
+        let one = BigUint::from(1_u32);
+        let x1 = (time + &one - ways) / 2_u32;
+        assert!(&x1 * (time - &x1) > *distance);
+        if x1 > BigUint::from(0_u32) {
+            let before = &x1 - &one;
+            assert!(&before * (time - &before) <= *distance);
+        }
+    }
+
+    #[test]
+    fn part2_large_concatenated_races() {
+        // Big enough that f64 can no longer represent time * time exactly.
+        let table = "Time:      71530 71530 71530 7153
+Distance:  9 40 200 9".parse::<RaceTable>().unwrap();
+        let (time, distance) = table.concatenated();
+        assert_eq!(time, "7153071530715307153");
+        let expected = ways_to_beat(&time.parse::<u128>().unwrap(), &distance.parse::<u128>().unwrap()).unwrap();
+        assert_eq!(table.part2(), expected as usize);
+        assert_eq!(table.part2_big(), BigUint::from(expected));
+        check_boundaries(&time.parse().unwrap(), &distance.parse().unwrap(), &table.part2_big());
+    }
+
+    #[test]
+    fn part2_arbitrary_precision() {
+        let table = format!("Time:{}\nDistance:{}", " 71530".repeat(12), " 940200".repeat(12)).parse::<RaceTable>().unwrap();
+        let (time, distance) = table.concatenated();
+        let time = time.parse::<BigUint>().unwrap();
+        let distance = distance.parse::<BigUint>().unwrap();
+        assert!(time.bits() > 128);
+        let ways = table.part2_big();
+        check_boundaries(&time, &distance, &ways);
+        assert!(ways > BigUint::from(0_u32));
+    }
 }
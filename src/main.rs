@@ -1,5 +1,22 @@
+mod day1;
+mod day2;
+mod day3;
+mod day4;
+mod day5;
+mod day6;
 mod day16;
 
 fn main() {
-    day16::main()
+    // `--day=N` picks the puzzle, the latest one by default.
+    let day = std::env::args().find_map(|arg| arg.strip_prefix("--day=").map(String::from));
+    match day.as_deref() {
+        Some("1") => day1::main(),
+        Some("2") => day2::main(),
+        Some("3") => day3::main(),
+        Some("4") => day4::main(),
+        Some("5") => day5::main(),
+        Some("6") => day6::main(),
+        None | Some("16") => day16::main(),
+        Some(day) => println!("error = unknown day {}", day),
+    }
 }